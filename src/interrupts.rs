@@ -1,12 +1,16 @@
 use crate::{
     gdt,
-    output::vga::{Color, WRITER},
-    pic, println,
+    memory::{lazy, user},
+    output::{self, vga::Color},
+    panicking, pic,
+    sync::IrqSafeMutex,
 };
 use lazy_static::lazy_static;
 use pic8259::ChainedPics;
-use spin::Mutex;
-use x86_64::structures::idt::{InterruptDescriptorTable, InterruptStackFrame};
+use x86_64::{
+    registers::control::Cr2,
+    structures::idt::{InterruptDescriptorTable, InterruptStackFrame, PageFaultErrorCode},
+};
 
 pub const PIC_1_OFFSET: u8 = 32;
 pub const PIC_2_OFFSET: u8 = PIC_1_OFFSET + 8;
@@ -28,8 +32,8 @@ impl InterruptIndex {
     }
 }
 
-pub static PICS: Mutex<ChainedPics> =
-    Mutex::new(unsafe { ChainedPics::new(PIC_1_OFFSET, PIC_2_OFFSET) });
+pub static PICS: IrqSafeMutex<ChainedPics> =
+    IrqSafeMutex::new(unsafe { ChainedPics::new(PIC_1_OFFSET, PIC_2_OFFSET) });
 
 lazy_static! {
     static ref IDT: InterruptDescriptorTable = {
@@ -42,6 +46,7 @@ lazy_static! {
                 .set_handler_fn(int_double_fault_handler)
                 .set_stack_index(gdt::DOUBLE_FAULT_IST_INDEX);
         }
+        idt.page_fault.set_handler_fn(int_page_fault_handler);
 
         idt[InterruptIndex::Timer.as_usize()].set_handler_fn(pic::timer::int_timer_handler);
         idt[InterruptIndex::Keyboard.as_usize()]
@@ -56,19 +61,50 @@ pub fn init_idt() {
 }
 
 extern "x86-interrupt" fn int_breakpoint_handler(_stack_frame: InterruptStackFrame) {
-    let fg = WRITER.lock().color_code.foreground_color;
-    WRITER.lock().color_code.foreground_color = Color::Yellow;
-    println!("\nException Raised: BREAKPOINT\n{:#?}\n", _stack_frame);
-    WRITER.lock().color_code.foreground_color = fg;
+    output::_print_colored(
+        Color::Yellow,
+        format_args!("\nException Raised: BREAKPOINT\n{:#?}\n\n", _stack_frame),
+    );
 }
 
 extern "x86-interrupt" fn int_double_fault_handler(
     _stack_frame: InterruptStackFrame,
     _err_code: u64,
 ) -> ! {
+    // Double faults mostly follow a page fault that could not be delivered, e.g. on a stack overflow.
+    panicking::record_fault_address(Cr2::read());
+
     panic!("\n\tException Raised: DOUBLE FAULT\n\t{:#?}", _stack_frame);
 }
 
+extern "x86-interrupt" fn int_page_fault_handler(
+    mut stack_frame: InterruptStackFrame,
+    error_code: PageFaultErrorCode,
+) {
+    let address = Cr2::read();
+    if lazy::handle_fault(address) {
+        return;
+    }
+
+    // Faulting user memory accesses of the copy helpers report an error instead.
+    if let Some(fixup) = user::fault_fixup(stack_frame.instruction_pointer) {
+        unsafe {
+            stack_frame
+                .as_mut()
+                .update(|frame| frame.instruction_pointer = fixup)
+        };
+        return;
+    }
+
+    panicking::record_fault_address(address);
+    panic!(
+        "\n\tException Raised: PAGE FAULT at {:#x} ({:?})\n\t{:#?}",
+        address.as_u64(),
+        error_code,
+        stack_frame
+    );
+}
+
 #[test_case]
 fn test_breakpoint_exception() {
     x86_64::instructions::interrupts::int3();
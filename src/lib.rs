@@ -12,10 +12,14 @@ extern crate alloc;
 
 pub mod gdt;
 pub mod interrupts;
+pub mod logging;
 pub mod memory;
 pub mod output;
+pub mod panicking;
+pub mod pci;
 pub mod pic;
 pub mod qemu;
+pub mod sync;
 pub mod task;
 
 use alloc::alloc::Layout;
@@ -23,7 +27,10 @@ use alloc::alloc::Layout;
 #[cfg(test)]
 use bootloader::{entry_point, BootInfo};
 
-use crate::qemu::{exit_qemu, QEMUExitCode};
+use crate::{
+    logging::LogConfig,
+    qemu::{exit_qemu, QEMUExitCode},
+};
 use core::panic::PanicInfo;
 
 pub trait Testable {
@@ -73,26 +80,27 @@ pub fn hlt_loop() -> ! {
 }
 
 pub fn init() {
+    memory::protection::enable();
+    logging::init(LogConfig::DEFAULT);
     gdt::init_gdt();
     interrupts::init_idt();
 
     unsafe {
         interrupts::PICS.lock().initialize();
     }
+    pic::timer::init();
     x86_64::instructions::interrupts::enable();
 }
 
 pub fn test_panic_handler(_info: &PanicInfo) -> ! {
-    serial_println!("[FAILED]\n\n! === Error Info === !");
-    serial_println!(
-        " Location: {}[{}:{}]",
-        _info.location().unwrap().file(),
-        _info.location().unwrap().line(),
-        _info.location().unwrap().column()
-    );
-    serial_println!(" Message: {:#?}", _info.message().unwrap());
-    // serial_println!(" Payload: {:#?}", _info.payload().downcast_ref::<&str>());
-    serial_println!("! === END === !\n");
+    panicking::prepare(_info);
+    serial_println!("[FAILED]\n");
+
+    // Tests report over serial, whatever the logger was configured with.
+    logging::init(LogConfig::SERIAL);
+    log::error!("! === Error Info === !");
+    panicking::log_details(_info);
+    log::error!("! === END === !\n");
 
     exit_qemu(QEMUExitCode::Failed);
 
@@ -100,6 +108,8 @@ pub fn test_panic_handler(_info: &PanicInfo) -> ! {
 }
 
 pub fn test_runner(tests: &[&dyn Testable]) {
+    // Tests report over serial, so make console output from the code under test visible there too.
+    output::mirror_to_serial(true);
     serial_println!("\nRunning {} test(s)...", tests.len());
 
     for test in tests {
@@ -0,0 +1,147 @@
+use crate::{pic::timer, sync::IrqSafeMutex};
+use core::{fmt, time::Duration};
+use log::{LevelFilter, Log, Metadata, Record};
+
+pub mod ring;
+pub mod sink;
+
+pub use ring::{dmesg, dump_serial};
+pub use sink::{ConsoleSink, LogSink, RingBufferSink, SerialSink};
+
+///
+/// Boot-time configuration of the kernel logger.
+///
+/// `filters` override `level` for targets starting with the given prefix,
+/// the longest matching prefix winning, e.g. `("nuclea_r_os::task", LevelFilter::Debug)`.
+///
+#[derive(Clone, Copy)]
+pub struct LogConfig {
+    pub level: LevelFilter,
+    pub filters: &'static [(&'static str, LevelFilter)],
+    pub sinks: &'static [&'static dyn LogSink],
+}
+
+impl LogConfig {
+    pub const DEFAULT: LogConfig = LogConfig {
+        level: LevelFilter::Info,
+        filters: &[],
+        sinks: &[&ConsoleSink, &RingBufferSink],
+    };
+
+    /// Logs to serial only, as used by the test harness.
+    pub const SERIAL: LogConfig = LogConfig {
+        sinks: &[&SerialSink],
+        ..LogConfig::DEFAULT
+    };
+
+    /// Returns the most verbose level any target may log at.
+    fn max_level(&self) -> LevelFilter {
+        self.filters
+            .iter()
+            .map(|&(_, level)| level)
+            .fold(self.level, LevelFilter::max)
+    }
+
+    /// Returns the level filter applying to `target`.
+    fn level_for(&self, target: &str) -> LevelFilter {
+        self.filters
+            .iter()
+            .filter(|(prefix, _)| target.starts_with(prefix))
+            .max_by_key(|(prefix, _)| prefix.len())
+            .map_or(self.level, |&(_, level)| level)
+    }
+}
+
+struct KernelLogger {
+    config: IrqSafeMutex<LogConfig>,
+}
+
+static LOGGER: KernelLogger = KernelLogger {
+    config: IrqSafeMutex::new(LogConfig::DEFAULT),
+};
+
+///
+/// Installs the kernel logger as the `log` facade backend and applies `config`. <br>
+/// Calling this again replaces the configuration.
+///
+pub fn init(config: LogConfig) {
+    *LOGGER.config.lock() = config;
+
+    // Only fails if the logger is already installed, which is fine when reconfiguring.
+    let _ = log::set_logger(&LOGGER);
+    log::set_max_level(config.max_level());
+}
+
+///
+/// Releases the logger's locks regardless of their holder. <br>
+/// Only for the panic handler, see `panicking::prepare`.
+///
+pub(crate) unsafe fn force_unlock() {
+    LOGGER.config.force_unlock();
+    ring::force_unlock();
+}
+
+impl Log for KernelLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= self.config.lock().level_for(metadata.target())
+    }
+
+    fn log(&self, record: &Record) {
+        use x86_64::instructions::interrupts;
+
+        // `without_interrupts` so a record reaches every sink before an interrupt handler's does
+        interrupts::without_interrupts(|| {
+            let config = *self.config.lock();
+            if record.level() > config.level_for(record.target()) {
+                return;
+            }
+
+            let uptime = timer::uptime();
+            for sink in config.sinks {
+                sink.log(uptime, record);
+            }
+        });
+    }
+
+    fn flush(&self) {}
+}
+
+/// Formats a log line as `[secs.millis] LEVEL target: message`, without a trailing newline.
+pub fn format_record(
+    writer: &mut impl fmt::Write,
+    uptime: Duration,
+    record: &Record,
+) -> fmt::Result {
+    write!(
+        writer,
+        "[{:>5}.{:03}] {:<5} {}: {}",
+        uptime.as_secs(),
+        uptime.subsec_millis(),
+        record.level(),
+        record.target(),
+        record.args()
+    )
+}
+
+#[test_case]
+fn test_level_filters() {
+    let config = LogConfig {
+        level: LevelFilter::Warn,
+        filters: &[
+            ("nuclea_r_os::task", LevelFilter::Debug),
+            ("nuclea_r_os::task::keyboard", LevelFilter::Off),
+        ],
+        sinks: &[],
+    };
+
+    assert_eq!(config.max_level(), LevelFilter::Debug);
+    assert_eq!(config.level_for("nuclea_r_os::memory"), LevelFilter::Warn);
+    assert_eq!(
+        config.level_for("nuclea_r_os::task::executor"),
+        LevelFilter::Debug
+    );
+    assert_eq!(
+        config.level_for("nuclea_r_os::task::keyboard"),
+        LevelFilter::Off
+    );
+}
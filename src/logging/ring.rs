@@ -0,0 +1,350 @@
+use crate::{console_println, serial_println, sync::IrqSafeMutex};
+use core::{fmt, time::Duration};
+use log::{Level, Record};
+
+const LOG_BUFFER_SIZE: usize = 16 * 1024;
+const MAX_MESSAGE_LEN: usize = 256;
+const HEADER_LEN: usize = 19; // Sequence (u64), uptime in ms (u64), level (u8), message length (u16)
+const PRINT_CHUNK: usize = 8; // Entries copied out per lock when printing
+
+static RING: IrqSafeMutex<LogRing> = IrqSafeMutex::new(LogRing::new());
+
+///
+/// A log record read back from the ring buffer.
+///
+#[derive(Debug, Clone, Copy)]
+pub struct LogEntry<'a> {
+    pub sequence: u64, // Increases by one per record, gaps mean evicted entries
+    pub uptime: Duration,
+    pub level: Level,
+    pub message: &'a str, // "target: message"
+}
+
+impl fmt::Display for LogEntry<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{:>6} [{:>5}.{:03}] {:<5} {}",
+            self.sequence,
+            self.uptime.as_secs(),
+            self.uptime.subsec_millis(),
+            self.level,
+            self.message
+        )
+    }
+}
+
+///
+/// Fixed-size byte ring holding variable-length entries, evicting the oldest when full. <br>
+/// Each entry is a `HEADER_LEN`-byte header followed by the message bytes.
+///
+struct LogRing {
+    buffer: [u8; LOG_BUFFER_SIZE],
+    head: usize, // Offset of the oldest entry
+    len: usize,  // Bytes in use
+    next_sequence: u64,
+}
+
+impl LogRing {
+    const fn new() -> Self {
+        LogRing {
+            buffer: [0; LOG_BUFFER_SIZE],
+            head: 0,
+            len: 0,
+            next_sequence: 0,
+        }
+    }
+
+    fn write_at(&mut self, offset: usize, bytes: &[u8]) {
+        for (i, &byte) in bytes.iter().enumerate() {
+            self.buffer[(offset + i) % LOG_BUFFER_SIZE] = byte;
+        }
+    }
+
+    fn read_at(&self, offset: usize, bytes: &mut [u8]) {
+        for (i, byte) in bytes.iter_mut().enumerate() {
+            *byte = self.buffer[(offset + i) % LOG_BUFFER_SIZE];
+        }
+    }
+
+    fn header_at(&self, offset: usize) -> (u64, Duration, Level, usize) {
+        let mut header = [0; HEADER_LEN];
+        self.read_at(offset, &mut header);
+
+        let mut sequence = [0; 8];
+        sequence.copy_from_slice(&header[0..8]);
+        let mut millis = [0; 8];
+        millis.copy_from_slice(&header[8..16]);
+        let level = match header[16] {
+            1 => Level::Error,
+            2 => Level::Warn,
+            3 => Level::Info,
+            4 => Level::Debug,
+            _ => Level::Trace,
+        };
+        let len = usize::from(u16::from_le_bytes([header[17], header[18]]));
+
+        (
+            u64::from_le_bytes(sequence),
+            Duration::from_millis(u64::from_le_bytes(millis)),
+            level,
+            len,
+        )
+    }
+
+    fn push(&mut self, uptime: Duration, level: Level, message: &[u8]) {
+        let entry_len = HEADER_LEN + message.len();
+
+        while LOG_BUFFER_SIZE - self.len < entry_len {
+            let (_, _, _, oldest_len) = self.header_at(self.head);
+            self.head = (self.head + HEADER_LEN + oldest_len) % LOG_BUFFER_SIZE;
+            self.len -= HEADER_LEN + oldest_len;
+        }
+
+        let mut header = [0; HEADER_LEN];
+        header[0..8].copy_from_slice(&self.next_sequence.to_le_bytes());
+        header[8..16].copy_from_slice(&(uptime.as_millis() as u64).to_le_bytes());
+        header[16] = level as u8;
+        header[17..19].copy_from_slice(&(message.len() as u16).to_le_bytes());
+        self.next_sequence += 1;
+
+        let tail = (self.head + self.len) % LOG_BUFFER_SIZE;
+        self.write_at(tail, &header);
+        self.write_at(tail + HEADER_LEN, message);
+        self.len += entry_len;
+    }
+
+    fn for_each(&self, mut f: impl FnMut(LogEntry)) {
+        self.for_each_from(0, |entry| {
+            f(entry);
+            true
+        });
+    }
+
+    /// Calls `f` with the entries from sequence number `first` on, until it returns `false`.
+    fn for_each_from(&self, first: u64, mut f: impl FnMut(LogEntry) -> bool) {
+        let mut offset = self.head;
+        let mut remaining = self.len;
+        let mut message = [0; MAX_MESSAGE_LEN];
+
+        while remaining > 0 {
+            let (sequence, uptime, level, len) = self.header_at(offset);
+
+            if sequence >= first {
+                self.read_at(offset + HEADER_LEN, &mut message[..len]);
+                let entry = LogEntry {
+                    sequence,
+                    uptime,
+                    level,
+                    message: core::str::from_utf8(&message[..len]).unwrap_or(""),
+                };
+                if !f(entry) {
+                    return;
+                }
+            }
+
+            offset = (offset + HEADER_LEN + len) % LOG_BUFFER_SIZE;
+            remaining -= HEADER_LEN + len;
+        }
+    }
+}
+
+/// Formats `record` and appends it to the ring buffer, truncating long messages.
+pub(super) fn push(uptime: Duration, record: &Record) {
+    use core::fmt::Write;
+
+    let mut message = MessageBuffer::new();
+    let _ = write!(message, "{}: {}", record.target(), record.args());
+
+    RING.lock().push(uptime, record.level(), message.as_bytes());
+}
+
+///
+/// Calls `f` with every retained entry, oldest first. <br>
+/// Interrupts are disabled for the duration to prevent deadlocks.
+///
+pub fn for_each(f: impl FnMut(LogEntry)) {
+    RING.lock().for_each(f);
+}
+
+/// Releases the ring buffer lock regardless of its holder, see `logging::force_unlock`.
+pub(super) unsafe fn force_unlock() {
+    RING.force_unlock();
+}
+
+///
+/// Calls `f` with every entry retained when called, oldest first, copying them out a few at a
+/// time so that the lock is not held while `f` runs. <br>
+/// `f` may therefore print or log; entries evicted meanwhile are skipped.
+///
+fn for_each_unlocked(mut f: impl FnMut(LogEntry)) {
+    let end = RING.lock().next_sequence;
+    let mut next = 0;
+
+    while next < end {
+        let mut chunk = [EntryCopy::EMPTY; PRINT_CHUNK];
+        let mut count = 0;
+        RING.lock().for_each_from(next, |entry| {
+            if entry.sequence >= end || count == PRINT_CHUNK {
+                return false;
+            }
+            chunk[count] = EntryCopy::new(&entry);
+            count += 1;
+            true
+        });
+
+        if count == 0 {
+            break;
+        }
+        chunk[..count].iter().for_each(|copy| f(copy.entry()));
+        next = chunk[count - 1].sequence + 1;
+    }
+}
+
+/// Prints every retained entry to virtual console `console`, like `dmesg`.
+pub fn dmesg(console: usize) {
+    for_each_unlocked(|entry| console_println!(console, "{}", entry));
+}
+
+///
+/// Writes every retained entry to the serial interface. <br>
+/// Used on panic so the log history reaches the host even if the screen is unreadable.
+///
+pub fn dump_serial() {
+    serial_println!("! === Kernel Log === !");
+    for_each_unlocked(|entry| serial_println!("{}", entry));
+    serial_println!("! === END === !");
+}
+
+/// A log entry copied out of the ring buffer, so that it can be used without holding the lock.
+#[derive(Clone, Copy)]
+struct EntryCopy {
+    sequence: u64,
+    uptime: Duration,
+    level: Level,
+    message: [u8; MAX_MESSAGE_LEN],
+    len: usize,
+}
+
+impl EntryCopy {
+    const EMPTY: Self = EntryCopy {
+        sequence: 0,
+        uptime: Duration::ZERO,
+        level: Level::Trace,
+        message: [0; MAX_MESSAGE_LEN],
+        len: 0,
+    };
+
+    fn new(entry: &LogEntry) -> Self {
+        let mut copy = EntryCopy {
+            sequence: entry.sequence,
+            uptime: entry.uptime,
+            level: entry.level,
+            len: entry.message.len(),
+            ..Self::EMPTY
+        };
+        copy.message[..copy.len].copy_from_slice(entry.message.as_bytes());
+        copy
+    }
+
+    fn entry(&self) -> LogEntry {
+        LogEntry {
+            sequence: self.sequence,
+            uptime: self.uptime,
+            level: self.level,
+            message: core::str::from_utf8(&self.message[..self.len]).unwrap_or(""),
+        }
+    }
+}
+
+///
+/// A fixed-capacity `fmt::Write` target that silently truncates at a char boundary. <br>
+/// Also used by `panicking`, which must format messages without allocating.
+///
+pub(crate) struct MessageBuffer {
+    buffer: [u8; MAX_MESSAGE_LEN],
+    len: usize,
+}
+
+impl MessageBuffer {
+    pub(crate) fn new() -> Self {
+        MessageBuffer {
+            buffer: [0; MAX_MESSAGE_LEN],
+            len: 0,
+        }
+    }
+
+    pub(crate) fn as_bytes(&self) -> &[u8] {
+        &self.buffer[..self.len]
+    }
+}
+
+impl fmt::Write for MessageBuffer {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        let mut count = s.len().min(MAX_MESSAGE_LEN - self.len);
+        while !s.is_char_boundary(count) {
+            count -= 1;
+        }
+
+        self.buffer[self.len..self.len + count].copy_from_slice(&s.as_bytes()[..count]);
+        self.len += count;
+        Ok(())
+    }
+}
+
+#[test_case]
+fn test_log_ring_eviction() {
+    let mut ring = LogRing::new();
+    let message = [b'x'; 200];
+
+    for i in 0..200 {
+        ring.push(Duration::from_millis(i), Level::Info, &message);
+    }
+
+    let mut count = 0;
+    let mut first = None;
+    let mut last = None;
+    ring.for_each(|entry| {
+        first.get_or_insert(entry.uptime);
+        if let Some(previous) = last {
+            assert_eq!(entry.sequence, previous + 1);
+        }
+        last = Some(entry.sequence);
+        assert_eq!(entry.message.len(), 200);
+        count += 1;
+    });
+
+    // Only the newest entries that fit are kept, in order.
+    let capacity = LOG_BUFFER_SIZE / (HEADER_LEN + 200);
+    assert_eq!(count, capacity);
+    assert_eq!(first, Some(Duration::from_millis(200 - capacity as u64)));
+    assert_eq!(last, Some(199));
+}
+
+#[test_case]
+fn test_for_each_unlocked() {
+    for i in 0..3 * PRINT_CHUNK as u64 {
+        RING.lock()
+            .push(Duration::from_millis(i), Level::Info, b"chunked");
+    }
+
+    let mut count = 0;
+    let mut last = None;
+    for_each_unlocked(|entry| {
+        // Pushing would deadlock if the ring were still locked, as when logging while printing.
+        RING.lock()
+            .push(Duration::ZERO, Level::Info, b"pushed while iterating");
+        if let Some(previous) = last {
+            assert!(entry.sequence > previous);
+        }
+        last = Some(entry.sequence);
+        count += 1;
+    });
+
+    // Entries pushed while iterating are not visited.
+    assert!(count >= 3 * PRINT_CHUNK);
+    assert_eq!(
+        last.map(|last| last + 1 + count as u64),
+        Some(RING.lock().next_sequence)
+    );
+}
@@ -0,0 +1,63 @@
+use super::{format_record, ring};
+use crate::{output, output::vga::Color, serial_println};
+use core::time::Duration;
+use log::{Level, Record};
+
+///
+/// A destination for kernel log records. <br>
+/// Sinks are called with interrupts disabled and must not log themselves.
+///
+pub trait LogSink: Sync {
+    fn log(&self, uptime: Duration, record: &Record);
+}
+
+/// Writes records to the active console, coloured by level.
+pub struct ConsoleSink;
+
+/// Writes records to the host console through the serial interface.
+pub struct SerialSink;
+
+/// Keeps records in the in-memory ring buffer in `logging::ring`.
+pub struct RingBufferSink;
+
+impl ConsoleSink {
+    fn color(level: Level) -> Color {
+        match level {
+            Level::Error => Color::Red,
+            Level::Warn => Color::Yellow,
+            Level::Info => Color::White,
+            Level::Debug => Color::LightGray,
+            Level::Trace => Color::DarkGray,
+        }
+    }
+}
+
+impl LogSink for ConsoleSink {
+    fn log(&self, uptime: Duration, record: &Record) {
+        output::_print_colored(
+            Self::color(record.level()),
+            format_args!("{}\n", Line(uptime, record)),
+        );
+    }
+}
+
+impl LogSink for SerialSink {
+    fn log(&self, uptime: Duration, record: &Record) {
+        serial_println!("{}", Line(uptime, record));
+    }
+}
+
+impl LogSink for RingBufferSink {
+    fn log(&self, uptime: Duration, record: &Record) {
+        ring::push(uptime, record);
+    }
+}
+
+/// Adapter to use `format_record` with formatting macros.
+struct Line<'a, 'b>(Duration, &'a Record<'b>);
+
+impl core::fmt::Display for Line<'_, '_> {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        format_record(f, self.0, self.1)
+    }
+}
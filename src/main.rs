@@ -12,13 +12,16 @@ extern crate alloc;
 use alloc::{boxed::Box, rc::Rc, vec, vec::Vec};
 use bootloader::{entry_point, BootInfo};
 use core::panic::PanicInfo;
+use log::LevelFilter;
 use nuclea_r_os::{
+    logging::{self, LogConfig},
     memory::{
-        heap,
-        paging::{init_offset_page_table, BootInfoFrameAllocator},
+        address_space,
+        frame::{self, GlobalFrameAllocator},
+        heap, protection,
     },
-    output::vga,
-    println,
+    output::{self, framebuffer, graphics, vga},
+    panicking, println,
     task::{executor::Executor, keyboard, Task},
 };
 use x86_64::{
@@ -28,18 +31,37 @@ use x86_64::{
 
 entry_point!(kernel_main);
 
+/// Kernel log settings applied at boot, e.g. `("nuclea_r_os::task", LevelFilter::Debug)` filters.
+const LOG_CONFIG: LogConfig = LogConfig {
+    level: LevelFilter::Info,
+    filters: &[],
+    ..LogConfig::DEFAULT
+};
+
 ///
 /// Kernel Entry Point
 ///
 fn kernel_main(_boot_info: &'static BootInfo) -> ! {
+    output::mirror_to_serial(cfg!(feature = "serial-mirror"));
     println!("Welcome! {}", ":D\n");
     nuclea_r_os::init();
+    logging::init(LOG_CONFIG);
 
     let phys_mem_offset = VirtAddr::new(_boot_info.physical_memory_offset);
-    let mut mapper = unsafe { init_offset_page_table(phys_mem_offset) };
-    let mut frame_allocator = unsafe { BootInfoFrameAllocator::new(&_boot_info.memory_map) };
-
-    heap::init_heap(&mut mapper, &mut frame_allocator).expect("Heap Initialization Failed.");
+    unsafe {
+        address_space::init(phys_mem_offset);
+        frame::init(&_boot_info.memory_map, phys_mem_offset);
+    }
+
+    address_space::with(|space| heap::init_heap(space.mapper(), &mut GlobalFrameAllocator))
+        .expect("Heap Initialization Failed.");
+    unsafe { protection::protect_kernel() };
+    vga::enable_scrollback(vga::DEFAULT_SCROLLBACK_LINES);
+    graphics::init(phys_mem_offset);
+    #[cfg(feature = "framebuffer-console")]
+    if let Err(error) = framebuffer::init(1024, 768) {
+        log::warn!("Framebuffer console unavailable: {:?}", error);
+    }
 
     #[cfg(test)]
     test_main();
@@ -65,21 +87,12 @@ async fn example_task() {
 #[cfg(not(test))]
 #[panic_handler]
 fn panic(_info: &PanicInfo) -> ! {
-    vga::WRITER.lock().color_code = vga::ColorCode {
-        foreground_color: vga::Color::Red,
-        background_color: vga::Color::Black,
-    };
-
-    println!("\n! === PANIC === !");
-    println!(
-        " Location: {}[{}:{}]",
-        _info.location().unwrap().file(),
-        _info.location().unwrap().line(),
-        _info.location().unwrap().column()
-    );
-    println!(" Message: \"{:#?}\"", _info.message().unwrap());
-    // println!(" Payload: {:#?}", _info.payload().downcast_ref::<&str>());
-    println!("! ============= !");
+    panicking::prepare(_info);
+
+    log::error!("! === PANIC === !");
+    panicking::log_details(_info);
+    log::error!("! ============= !");
+    logging::dump_serial();
 
     nuclea_r_os::hlt_loop();
 }
@@ -0,0 +1,621 @@
+use super::{
+    buddy::Zone,
+    frame::{self, GlobalFrameAllocator},
+    paging,
+};
+use crate::sync::IrqSafeMutex;
+use core::arch::x86_64::__cpuid;
+use x86_64::{
+    instructions::tlb,
+    registers::model_specific::{Efer, EferFlags},
+    structures::paging::{
+        mapper::{MapToError, MapperFlush, TranslateResult},
+        FrameAllocator, FrameDeallocator, Mapper, OffsetPageTable, Page, PageSize, PageTable,
+        PageTableEntry, PageTableFlags as PTFlags, PhysFrame, Size1GiB, Size2MiB, Size4KiB,
+        Translate,
+    },
+    PhysAddr, VirtAddr,
+};
+
+const PAGE_SIZE: u64 = Size4KiB::SIZE;
+const HUGE_PAGE_ORDER: usize = 9; // Buddy order of the frames backing a 2 MiB page
+const FLUSH_ALL_THRESHOLD: u64 = 32; // Pages changed at once beyond which the whole TLB is flushed
+
+static KERNEL_SPACE: IrqSafeMutex<Option<AddressSpace>> = IrqSafeMutex::new(None);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MapError {
+    OutOfMemory,
+    AlreadyMapped, // Part of the range is mapped already, or covered by a huge page
+    NotMapped,     // Part of the range is not mapped
+}
+
+///
+/// Access rights of a mapping, translated to page table flags. <br>
+/// Pages are always readable by the kernel, and only executable if the permissions say so
+/// once the CPU enforces it.
+///
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Permissions {
+    writable: bool,
+    executable: bool,
+    user: bool,
+    uncached: bool,
+}
+
+///
+/// The page tables of the active address space, behind the `KERNEL_SPACE` lock. <br>
+/// All changes flush the TLB entries they affect.
+///
+pub struct AddressSpace {
+    mapper: OffsetPageTable<'static>,
+}
+
+/// Sets up the kernel address space from the active page tables, used through `with`.
+///
+/// This function is unsafe as it is up to the caller to ensure that the
+/// complete physical memory is mapped to virtual memory at the passed
+/// `physical_memory_offset`. No other `OffsetPageTable` may be created
+/// for the active level 4 table afterwards.
+pub unsafe fn init(physical_memory_offset: VirtAddr) {
+    let mapper = paging::init_offset_page_table(physical_memory_offset);
+    *KERNEL_SPACE.lock() = Some(AddressSpace { mapper });
+}
+
+///
+/// Runs `f` with the kernel address space, panicking if `init` has not been called. <br>
+/// `f` must not allocate on the heap, as growing the heap needs the address space too.
+///
+pub fn with<R>(f: impl FnOnce(&mut AddressSpace) -> R) -> R {
+    f(KERNEL_SPACE
+        .lock()
+        .as_mut()
+        .expect("Address space not initialized."))
+}
+
+/// Like `with`, but returns `None` if the address space is not initialized or already in use.
+pub fn try_with<R>(f: impl FnOnce(&mut AddressSpace) -> R) -> Option<R> {
+    KERNEL_SPACE.try_lock()?.as_mut().map(f)
+}
+
+impl Permissions {
+    pub const READ: Permissions = Permissions {
+        writable: false,
+        executable: false,
+        user: false,
+        uncached: false,
+    };
+    pub const READ_WRITE: Permissions = Permissions {
+        writable: true,
+        ..Permissions::READ
+    };
+    pub const READ_EXECUTE: Permissions = Permissions {
+        executable: true,
+        ..Permissions::READ
+    };
+
+    /// Returns these permissions, also granting them to user mode.
+    pub const fn user(self) -> Permissions {
+        Permissions { user: true, ..self }
+    }
+
+    /// Returns these permissions with caching disabled, e.g. for device registers.
+    pub const fn uncached(self) -> Permissions {
+        Permissions {
+            uncached: true,
+            ..self
+        }
+    }
+
+    pub fn is_writable(&self) -> bool {
+        self.writable
+    }
+
+    pub fn is_executable(&self) -> bool {
+        self.executable
+    }
+
+    pub fn is_user(&self) -> bool {
+        self.user
+    }
+
+    pub fn is_uncached(&self) -> bool {
+        self.uncached
+    }
+
+    /// Returns the page table flags granting the permissions.
+    pub fn flags(&self) -> PTFlags {
+        let mut flags = PTFlags::PRESENT;
+        flags.set(PTFlags::WRITABLE, self.writable);
+        flags.set(PTFlags::USER_ACCESSIBLE, self.user);
+        flags.set(PTFlags::NO_CACHE | PTFlags::WRITE_THROUGH, self.uncached);
+
+        // The bit is reserved, and faults, until no-execute is enabled.
+        let no_execute = Efer::read().contains(EferFlags::NO_EXECUTE_ENABLE);
+        flags.set(PTFlags::NO_EXECUTE, no_execute && !self.executable);
+        flags
+    }
+
+    fn from_flags(flags: PTFlags) -> Permissions {
+        Permissions {
+            writable: flags.contains(PTFlags::WRITABLE),
+            executable: !flags.contains(PTFlags::NO_EXECUTE),
+            user: flags.contains(PTFlags::USER_ACCESSIBLE),
+            uncached: flags.contains(PTFlags::NO_CACHE),
+        }
+    }
+}
+
+impl AddressSpace {
+    ///
+    /// Maps the pages covering `size` bytes from `start` to newly allocated frames. <br>
+    /// Aligned 2 MiB stretches get huge pages while contiguous frames are available.
+    /// Nothing stays mapped if any page fails.
+    ///
+    pub fn map_range(
+        &mut self,
+        start: VirtAddr,
+        size: usize,
+        permissions: Permissions,
+    ) -> Result<(), MapError> {
+        let flags = permissions.flags();
+        let (first, end) = page_bounds(start, size);
+        let mut flusher = Flusher::new(first, end);
+        let mut address = first;
+
+        while address < end {
+            let result = match self.map_fresh_huge_page(address, end, flags, &mut flusher) {
+                Some(size) => Ok(size),
+                None => self.map_fresh_page(address, flags, &mut flusher),
+            };
+
+            match result {
+                Ok(size) => address += size,
+                Err(error) => {
+                    self.unmap_pages(first, address, true, &mut flusher)
+                        .expect("Failed to roll back a partial mapping.");
+                    flusher.finish();
+                    return Err(error);
+                }
+            }
+        }
+
+        flusher.finish();
+        Ok(())
+    }
+
+    ///
+    /// Maps the pages covering `size` bytes from `start` to the physical memory at
+    /// `physical_start`, which must have the same offset within its page. <br>
+    /// Stretches aligned alike in both get 2 MiB or, if supported, 1 GiB pages.
+    /// Nothing stays mapped if any page fails.
+    ///
+    pub fn map_physical_range(
+        &mut self,
+        start: VirtAddr,
+        physical_start: PhysAddr,
+        size: usize,
+        permissions: Permissions,
+    ) -> Result<(), MapError> {
+        assert_eq!(
+            start.as_u64() % PAGE_SIZE,
+            physical_start.as_u64() % PAGE_SIZE,
+            "Virtual and physical start differ in their page offset."
+        );
+
+        let flags = permissions.flags();
+        let (first, end) = page_bounds(start, size);
+        let physical_first = physical_start.align_down(PAGE_SIZE);
+        let mut flusher = Flusher::new(first, end);
+        let mut address = first;
+
+        while address < end {
+            let physical = physical_first + (address - first);
+            let result = self.map_physical_page(address, physical, end, flags, &mut flusher);
+
+            match result {
+                Ok(size) => address += size,
+                Err(error) => {
+                    self.unmap_pages(first, address, false, &mut flusher)
+                        .expect("Failed to roll back a partial mapping.");
+                    flusher.finish();
+                    return Err(error);
+                }
+            }
+        }
+
+        flusher.finish();
+        Ok(())
+    }
+
+    /// Unmaps the pages covering `size` bytes from `start`, returning their frames to the frame allocator.
+    ///
+    /// This function is unsafe as it is up to the caller to ensure that the memory is no
+    /// longer in use and that its frames were allocated for it, as by `map_range`. Huge pages
+    /// only partly in the range are split, which fails if no frame is left for a page table.
+    pub unsafe fn unmap_range(&mut self, start: VirtAddr, size: usize) -> Result<(), MapError> {
+        let (first, end) = page_bounds(start, size);
+        let mut flusher = Flusher::new(first, end);
+        let result = self.unmap_pages(first, end, true, &mut flusher);
+        flusher.finish();
+        result
+    }
+
+    /// Unmaps the pages covering `size` bytes from `start`, leaving the memory they mapped alone.
+    ///
+    /// This function is unsafe as it is up to the caller to ensure that the memory is no
+    /// longer in use. Huge pages only partly in the range are split, which fails if no frame
+    /// is left for a page table.
+    pub unsafe fn unmap_physical_range(
+        &mut self,
+        start: VirtAddr,
+        size: usize,
+    ) -> Result<(), MapError> {
+        let (first, end) = page_bounds(start, size);
+        let mut flusher = Flusher::new(first, end);
+        let result = self.unmap_pages(first, end, false, &mut flusher);
+        flusher.finish();
+        result
+    }
+
+    /// Changes the permissions of the mapped pages covering `size` bytes from `start`.
+    ///
+    /// This function is unsafe as it is up to the caller to ensure that no code relies on
+    /// the permissions being taken away, e.g. by writing to memory made read-only. Huge pages
+    /// only partly in the range are split first.
+    pub unsafe fn protect(
+        &mut self,
+        start: VirtAddr,
+        size: usize,
+        permissions: Permissions,
+    ) -> Result<(), MapError> {
+        let flags = permissions.flags();
+        let (first, end) = page_bounds(start, size);
+        let mut flusher = Flusher::new(first, end);
+        let mut address = first;
+
+        let result = loop {
+            if address >= end {
+                break Ok(());
+            }
+
+            let (page_start, size) = match self.mapped_page(address) {
+                Some(page) => page,
+                None => break Err(MapError::NotMapped),
+            };
+            if page_start < first || page_start + size > end {
+                if let Err(error) = self.split(page_start, size) {
+                    break Err(error);
+                }
+                continue;
+            }
+
+            let updated = match size {
+                Size4KiB::SIZE => self
+                    .mapper
+                    .update_flags(Page::<Size4KiB>::containing_address(address), flags)
+                    .map(|flush| flusher.add(flush)),
+                Size2MiB::SIZE => self
+                    .mapper
+                    .update_flags(Page::<Size2MiB>::containing_address(address), flags)
+                    .map(|flush| flusher.add(flush)),
+                _ => self
+                    .mapper
+                    .update_flags(Page::<Size1GiB>::containing_address(address), flags)
+                    .map(|flush| flusher.add(flush)),
+            };
+            if updated.is_err() {
+                break Err(MapError::NotMapped);
+            }
+            address = page_start + size;
+        };
+
+        flusher.finish();
+        result
+    }
+
+    /// Returns the physical address `address` is mapped to, if any.
+    pub fn translate(&self, address: VirtAddr) -> Option<PhysAddr> {
+        self.mapper.translate_addr(address)
+    }
+
+    /// Returns the permissions of the page containing `address`, if mapped.
+    pub fn permissions(&self, address: VirtAddr) -> Option<Permissions> {
+        match self.mapper.translate(address) {
+            TranslateResult::Mapped { flags, .. } => Some(Permissions::from_flags(flags)),
+            _ => None,
+        }
+    }
+
+    /// Returns the size of the page containing `address`, if mapped.
+    pub fn page_size(&self, address: VirtAddr) -> Option<u64> {
+        self.mapped_page(address).map(|(_, size)| size)
+    }
+
+    ///
+    /// Returns the underlying mapper, for code that takes one. <br>
+    /// Changes made through it must flush the TLB themselves.
+    ///
+    pub fn mapper(&mut self) -> &mut OffsetPageTable<'static> {
+        &mut self.mapper
+    }
+
+    ///
+    /// Maps a 2 MiB page of fresh frames at `address` if it starts one within `end`. <br>
+    /// Returns `None` to fall back to 4 KiB pages, e.g. if a page table left by earlier
+    /// mappings is in the way.
+    ///
+    fn map_fresh_huge_page(
+        &mut self,
+        address: VirtAddr,
+        end: VirtAddr,
+        flags: PTFlags,
+        flusher: &mut Flusher,
+    ) -> Option<u64> {
+        if !address.is_aligned(Size2MiB::SIZE) || end - address < Size2MiB::SIZE {
+            return None;
+        }
+
+        let frame = frame::allocate_contiguous(HUGE_PAGE_ORDER, Zone::Normal)?;
+        let page = Page::<Size2MiB>::from_start_address(address).unwrap();
+        let huge_frame = PhysFrame::from_start_address(frame.start_address()).unwrap();
+
+        let result = self.map_page(page, huge_frame, flags, flusher);
+        if result.is_err() {
+            unsafe { frame::deallocate_contiguous(frame, HUGE_PAGE_ORDER) };
+        }
+        result.ok()
+    }
+
+    ///
+    /// Maps the largest page fitting at `address` before `end` to `physical`, falling back
+    /// to smaller pages if page tables left by earlier mappings are in the way. <br>
+    /// Returns the size of the page mapped.
+    ///
+    fn map_physical_page(
+        &mut self,
+        address: VirtAddr,
+        physical: PhysAddr,
+        end: VirtAddr,
+        flags: PTFlags,
+        flusher: &mut Flusher,
+    ) -> Result<u64, MapError> {
+        let fits = |size: u64| {
+            address.is_aligned(size) && physical.is_aligned(size) && end - address >= size
+        };
+
+        if has_giga_pages() && fits(Size1GiB::SIZE) {
+            let page = Page::<Size1GiB>::from_start_address(address).unwrap();
+            let frame = PhysFrame::from_start_address(physical).unwrap();
+            if let Ok(size) = self.map_page(page, frame, flags, flusher) {
+                return Ok(size);
+            }
+        }
+        if fits(Size2MiB::SIZE) {
+            let page = Page::<Size2MiB>::from_start_address(address).unwrap();
+            let frame = PhysFrame::from_start_address(physical).unwrap();
+            if let Ok(size) = self.map_page(page, frame, flags, flusher) {
+                return Ok(size);
+            }
+        }
+
+        let page = Page::<Size4KiB>::from_start_address(address).unwrap();
+        let frame = PhysFrame::from_start_address(physical).unwrap();
+        self.map_page(page, frame, flags, flusher)
+    }
+
+    fn map_fresh_page(
+        &mut self,
+        address: VirtAddr,
+        flags: PTFlags,
+        flusher: &mut Flusher,
+    ) -> Result<u64, MapError> {
+        let frame = GlobalFrameAllocator
+            .allocate_frame()
+            .ok_or(MapError::OutOfMemory)?;
+        let page = Page::<Size4KiB>::from_start_address(address).unwrap();
+
+        self.map_page(page, frame, flags, flusher).map_err(|error| {
+            unsafe { GlobalFrameAllocator.deallocate_frame(frame) };
+            error
+        })
+    }
+
+    /// Maps `page` to `frame`, returning the page size.
+    fn map_page<S: PageSize>(
+        &mut self,
+        page: Page<S>,
+        frame: PhysFrame<S>,
+        flags: PTFlags,
+        flusher: &mut Flusher,
+    ) -> Result<u64, MapError>
+    where
+        OffsetPageTable<'static>: Mapper<S>,
+    {
+        // Intermediate tables stay writable, so that the last level decides.
+        let parent_flags =
+            PTFlags::PRESENT | PTFlags::WRITABLE | (flags & PTFlags::USER_ACCESSIBLE);
+        let flush = unsafe {
+            self.mapper.map_to_with_table_flags(
+                page,
+                frame,
+                flags,
+                parent_flags,
+                &mut GlobalFrameAllocator,
+            )
+        };
+
+        match flush {
+            Ok(flush) => {
+                flusher.add(flush);
+                Ok(S::SIZE)
+            }
+            Err(MapToError::FrameAllocationFailed) => Err(MapError::OutOfMemory),
+            Err(MapToError::ParentEntryHugePage | MapToError::PageAlreadyMapped(_)) => {
+                Err(MapError::AlreadyMapped)
+            }
+        }
+    }
+
+    /// Unmaps all pages from `start` to `end`, splitting huge pages that reach past either.
+    fn unmap_pages(
+        &mut self,
+        start: VirtAddr,
+        end: VirtAddr,
+        free_frames: bool,
+        flusher: &mut Flusher,
+    ) -> Result<(), MapError> {
+        let mut address = start;
+
+        while address < end {
+            let (page_start, size) = match self.mapped_page(address) {
+                Some(page) => page,
+                None => {
+                    address += PAGE_SIZE;
+                    continue;
+                }
+            };
+            if page_start < start || page_start + size > end {
+                self.split(page_start, size)?;
+                continue;
+            }
+
+            let frame_start = match size {
+                Size4KiB::SIZE => {
+                    self.unmap_page(Page::<Size4KiB>::containing_address(address), flusher)
+                }
+                Size2MiB::SIZE => {
+                    self.unmap_page(Page::<Size2MiB>::containing_address(address), flusher)
+                }
+                _ => self.unmap_page(Page::<Size1GiB>::containing_address(address), flusher),
+            };
+            if free_frames {
+                // The buddy allocator merges the frames back into larger blocks.
+                let first = PhysFrame::<Size4KiB>::containing_address(frame_start);
+                for frame in PhysFrame::range(first, first + size / PAGE_SIZE) {
+                    unsafe { GlobalFrameAllocator.deallocate_frame(frame) };
+                }
+            }
+            address = page_start + size;
+        }
+        Ok(())
+    }
+
+    /// Unmaps the mapped `page`, returning the start of the memory it mapped.
+    fn unmap_page<S: PageSize>(&mut self, page: Page<S>, flusher: &mut Flusher) -> PhysAddr
+    where
+        OffsetPageTable<'static>: Mapper<S>,
+    {
+        let (frame, flush) = self
+            .mapper
+            .unmap(page)
+            .unwrap_or_else(|error| panic!("Failed to unmap {:?}: {:?}", page, error));
+        flusher.add(flush);
+        frame.start_address()
+    }
+
+    /// Returns the start and size of the page containing `address`, if mapped.
+    fn mapped_page(&self, address: VirtAddr) -> Option<(VirtAddr, u64)> {
+        match self.mapper.translate(address) {
+            TranslateResult::Mapped { frame, .. } => {
+                let size = frame.size();
+                Some((address.align_down(size), size))
+            }
+            _ => None,
+        }
+    }
+
+    ///
+    /// Replaces the huge page of `size` at `start` by a table of 512 pages of the next smaller
+    /// size with the same flags, mapping the same memory. <br>
+    /// Fails if no frame is left for the table.
+    ///
+    fn split(&mut self, start: VirtAddr, size: u64) -> Result<(), MapError> {
+        let table_frame = GlobalFrameAllocator
+            .allocate_frame()
+            .ok_or(MapError::OutOfMemory)?;
+        let table_address = self.mapper.phys_offset() + table_frame.start_address().as_u64();
+        let table = unsafe { &mut *table_address.as_mut_ptr::<PageTable>() };
+        table.zero();
+
+        let level = if size == Size2MiB::SIZE { 2 } else { 3 };
+        let entry = self.entry_mut(start, level);
+        let (base, flags) = (entry.addr(), entry.flags());
+
+        // Pages split from a 1 GiB page are 2 MiB pages themselves.
+        let child_size = size / 512;
+        let child_flags = match level {
+            2 => flags - PTFlags::HUGE_PAGE,
+            _ => flags,
+        };
+        for (index, child) in table.iter_mut().enumerate() {
+            child.set_addr(base + index as u64 * child_size, child_flags);
+        }
+
+        let parent_flags =
+            PTFlags::PRESENT | PTFlags::WRITABLE | (flags & PTFlags::USER_ACCESSIBLE);
+        entry.set_addr(table_frame.start_address(), parent_flags);
+        tlb::flush(start);
+        Ok(())
+    }
+
+    /// Returns the entry for `address` in the table of `level`, whose parents must be present.
+    fn entry_mut(&mut self, address: VirtAddr, level: usize) -> &mut PageTableEntry {
+        let indices = [
+            address.p4_index(),
+            address.p3_index(),
+            address.p2_index(),
+            address.p1_index(),
+        ];
+        let offset = self.mapper.phys_offset();
+        let mut table = self.mapper.level_4_table();
+
+        for index in &indices[..4 - level] {
+            let next = offset + table[*index].addr().as_u64();
+            table = unsafe { &mut *next.as_mut_ptr::<PageTable>() };
+        }
+        &mut table[indices[4 - level]]
+    }
+}
+
+/// Returns `true` if the CPU supports 1 GiB pages.
+pub fn has_giga_pages() -> bool {
+    let max_extended_leaf = unsafe { __cpuid(0x8000_0000) }.eax;
+    max_extended_leaf >= 0x8000_0001 && unsafe { __cpuid(0x8000_0001) }.edx & (1 << 26) != 0
+}
+
+///
+/// Flushes the TLB entries of changed pages, or the whole TLB once so many pages change
+/// that flushing them one by one would be slower.
+///
+struct Flusher {
+    flush_all: bool,
+}
+
+impl Flusher {
+    fn new(start: VirtAddr, end: VirtAddr) -> Self {
+        Flusher {
+            flush_all: (end - start) / PAGE_SIZE > FLUSH_ALL_THRESHOLD,
+        }
+    }
+
+    fn add<S: PageSize>(&mut self, flush: MapperFlush<S>) {
+        if self.flush_all {
+            flush.ignore();
+        } else {
+            flush.flush();
+        }
+    }
+
+    fn finish(self) {
+        if self.flush_all {
+            tlb::flush_all();
+        }
+    }
+}
+
+/// Returns the page-aligned bounds of `size` bytes from `start`.
+fn page_bounds(start: VirtAddr, size: usize) -> (VirtAddr, VirtAddr) {
+    let end = start + size.max(1) as u64;
+    (start.align_down(PAGE_SIZE), end.align_up(PAGE_SIZE))
+}
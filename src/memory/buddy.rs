@@ -0,0 +1,329 @@
+use bootloader::bootinfo::{MemoryMap, MemoryRegionType};
+use core::{ops::Range, slice};
+use x86_64::{
+    structures::paging::{FrameAllocator, FrameDeallocator, PhysFrame, Size4KiB},
+    PhysAddr, VirtAddr,
+};
+
+/// Largest block order: blocks span `4 KiB << MAX_ORDER`, i.e. 4 MiB.
+pub const MAX_ORDER: usize = 10;
+
+const ORDER_COUNT: usize = MAX_ORDER + 1;
+const ZONE_COUNT: usize = 3;
+const FRAME_SIZE: u64 = 4096;
+
+// Per-frame states, besides `0..=MAX_ORDER` marking the first frame of a free block of that order.
+const USED: u8 = 0xff; // Allocated
+const FREE: u8 = 0xfe; // Part of a free block, but not its first frame
+const RESERVED: u8 = 0xfd; // Reserved or not usable memory, never handed out
+
+///
+/// Physical address ranges for devices that cannot reach all of memory. <br>
+/// Zone boundaries are aligned to the largest block, so no block spans two zones.
+///
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Zone {
+    Dma,    // Below 16 MiB, reachable by ISA DMA
+    Dma32,  // Below 4 GiB, reachable by 32-bit devices
+    Normal, // Anywhere
+}
+
+impl Zone {
+    fn of(frame: usize) -> Zone {
+        match frame as u64 * FRAME_SIZE {
+            address if address < 16 << 20 => Zone::Dma,
+            address if address < 4 << 30 => Zone::Dma32,
+            _ => Zone::Normal,
+        }
+    }
+}
+
+/// Returns the smallest order whose blocks hold `size` bytes.
+pub fn order_for(size: usize) -> usize {
+    let frames = (size as u64 + FRAME_SIZE - 1) / FRAME_SIZE;
+    frames.max(1).next_power_of_two().trailing_zeros() as usize
+}
+
+/// Links of a free block's list, stored in the first bytes of the block itself.
+#[derive(Clone, Copy)]
+struct FreeNode {
+    previous: Option<usize>,
+    next: Option<usize>,
+}
+
+///
+/// A buddy allocator over physical memory, handing out blocks of `2^order` frames
+/// aligned to their size. <br>
+/// Free blocks are kept in per-zone, per-order doubly linked lists threaded through the
+/// blocks themselves, which are accessed through the physical memory mapping. One state
+/// byte per frame, stored in the first usable region large enough to hold it, records
+/// which frames are free and where free blocks start, so that freed blocks can be
+/// coalesced with their buddy.
+///
+pub struct BuddyAllocator {
+    physical_memory_offset: VirtAddr,
+    states: &'static mut [u8],
+    free_lists: [[Option<usize>; ORDER_COUNT]; ZONE_COUNT], // First frame of each list's head
+    total: usize, // Frames available for allocation, excluding reserved ones
+    used: usize,
+}
+
+impl BuddyAllocator {
+    /// Create a BuddyAllocator from the passed memory map.
+    ///
+    /// This function is unsafe as it is up to the caller to ensure that the complete physical
+    /// memory is mapped at `physical_memory_offset` and that all frames marked as `USABLE` in
+    /// the memory map are actually unused.
+    pub unsafe fn new(memory_map: &'static MemoryMap, physical_memory_offset: VirtAddr) -> Self {
+        let usable_regions = || {
+            memory_map
+                .iter()
+                .filter(|r| r.region_type == MemoryRegionType::Usable)
+                .map(|r| r.range.start_frame_number as usize..r.range.end_frame_number as usize)
+        };
+
+        let frame_count = usable_regions().map(|r| r.end).max().unwrap_or(0);
+        let storage_frames = (frame_count as u64 + FRAME_SIZE - 1) / FRAME_SIZE;
+        let storage = usable_regions()
+            .find(|r| (r.end - r.start) as u64 >= storage_frames)
+            .expect("No usable region large enough for the frame states.")
+            .start;
+
+        let states_ptr: *mut u8 =
+            (physical_memory_offset + storage as u64 * FRAME_SIZE).as_mut_ptr();
+        let states = slice::from_raw_parts_mut(states_ptr, frame_count);
+        states.fill(RESERVED);
+
+        let mut allocator = BuddyAllocator {
+            physical_memory_offset,
+            states,
+            free_lists: [[None; ORDER_COUNT]; ZONE_COUNT],
+            total: 0,
+            used: 0,
+        };
+
+        // Freeing frame by frame coalesces adjacent regions into the largest possible blocks.
+        let reserved = storage..storage + storage_frames as usize;
+        for frame in usable_regions().flatten().filter(|f| !reserved.contains(f)) {
+            allocator.total += 1;
+            allocator.used += 1;
+            allocator.free(frame, 0);
+        }
+
+        allocator
+    }
+
+    /// Returns the number of frames available for allocation, whether free or in use.
+    pub fn total_frames(&self) -> usize {
+        self.total
+    }
+
+    /// Returns the number of allocated frames.
+    pub fn used_frames(&self) -> usize {
+        self.used
+    }
+
+    /// Returns the number of frames that can still be allocated.
+    pub fn free_frames(&self) -> usize {
+        self.total - self.used
+    }
+
+    /// Returns the number of free blocks of `order` in `zone`.
+    pub fn free_blocks(&self, zone: Zone, order: usize) -> usize {
+        let mut count = 0;
+        let mut head = self.free_lists[zone as usize][order];
+
+        while let Some(frame) = head {
+            count += 1;
+            head = self.node(frame).next;
+        }
+        count
+    }
+
+    ///
+    /// Allocates `2^order` contiguous frames aligned to their size, taken from `zone`
+    /// or, if it is exhausted, from the zones below it.
+    ///
+    pub fn allocate(&mut self, order: usize, zone: Zone) -> Option<PhysFrame> {
+        assert!(order <= MAX_ORDER, "Block order {} too large.", order);
+
+        let (zone, found_order) = (0..=zone as usize)
+            .rev()
+            .flat_map(|zone| (order..=MAX_ORDER).map(move |found| (zone, found)))
+            .find(|&(zone, found)| self.free_lists[zone][found].is_some())?;
+
+        let block = self.free_lists[zone][found_order].unwrap();
+        self.unlink(block, found_order);
+
+        // Split off the upper halves until the block has the requested size.
+        for lower in (order..found_order).rev() {
+            self.link(block + (1 << lower), lower);
+        }
+
+        self.states[block..block + (1 << order)].fill(USED);
+        self.used += 1 << order;
+
+        Some(frame_at(block))
+    }
+
+    /// Frees a block returned by `allocate` with the same `order`.
+    ///
+    /// This function is unsafe as it is up to the caller to ensure that the block is no
+    /// longer in use.
+    pub unsafe fn deallocate(&mut self, frame: PhysFrame, order: usize) {
+        let index = frame_index(frame);
+        assert!(
+            index % (1 << order) == 0 && index + (1 << order) <= self.states.len(),
+            "Invalid block {:?} of order {}.",
+            frame,
+            order
+        );
+        let states = &self.states[index..index + (1 << order)];
+        assert!(
+            !states.contains(&RESERVED),
+            "Free of block {:?} of order {}, which holds reserved or unusable frames.",
+            frame,
+            order
+        );
+        assert!(
+            states.iter().all(|&state| state == USED),
+            "Double free of block {:?}.",
+            frame
+        );
+
+        self.free(index, order);
+    }
+
+    ///
+    /// Withdraws the free frames overlapping `range` from allocation, e.g. for firmware tables
+    /// or device memory. <br>
+    /// Frames already allocated are left alone and become available again once deallocated.
+    ///
+    pub fn reserve(&mut self, range: Range<PhysAddr>) {
+        let start = frame_index(PhysFrame::containing_address(range.start));
+        let end = frame_index(PhysFrame::containing_address(
+            range.end.align_up(FRAME_SIZE),
+        ));
+
+        for index in start..end.min(self.states.len()) {
+            if self.states[index] != USED && self.states[index] != RESERVED {
+                self.isolate(index);
+                self.states[index] = RESERVED;
+                self.total -= 1;
+            }
+        }
+    }
+
+    /// Returns `true` if `frame` is allocated, reserved or not usable memory.
+    pub fn is_used(&self, frame: PhysFrame) -> bool {
+        self.states
+            .get(frame_index(frame))
+            .map_or(true, |&state| state == USED || state == RESERVED)
+    }
+
+    /// Frees the block of `order` at `index`, merging it with its buddy while that is free.
+    fn free(&mut self, mut index: usize, mut order: usize) {
+        self.states[index..index + (1 << order)].fill(FREE);
+        self.used -= 1 << order;
+
+        while order < MAX_ORDER {
+            let buddy = index ^ (1 << order);
+            if self.states.get(buddy) != Some(&(order as u8)) {
+                break;
+            }
+
+            self.unlink(buddy, order);
+            index = index.min(buddy);
+            order += 1;
+        }
+
+        self.link(index, order);
+    }
+
+    /// Splits the free block containing the free frame `index` until `index` is a block of its own, then unlinks it.
+    fn isolate(&mut self, index: usize) {
+        let (mut block, mut order) = (0..=MAX_ORDER)
+            .map(|order| (index & !((1 << order) - 1), order))
+            .find(|&(block, order)| self.states[block] == order as u8)
+            .expect("Free frame outside of any free block.");
+        self.unlink(block, order);
+
+        while order > 0 {
+            order -= 1;
+            let upper = block + (1 << order);
+            if index >= upper {
+                self.link(block, order);
+                block = upper;
+            } else {
+                self.link(upper, order);
+            }
+        }
+    }
+
+    fn link(&mut self, block: usize, order: usize) {
+        let zone = Zone::of(block) as usize;
+        let next = self.free_lists[zone][order];
+
+        if let Some(next) = next {
+            self.node_mut(next).previous = Some(block);
+        }
+        *self.node_mut(block) = FreeNode {
+            previous: None,
+            next,
+        };
+        self.free_lists[zone][order] = Some(block);
+        self.states[block] = order as u8;
+    }
+
+    fn unlink(&mut self, block: usize, order: usize) {
+        let zone = Zone::of(block) as usize;
+        let node = *self.node(block);
+
+        match node.previous {
+            Some(previous) => self.node_mut(previous).next = node.next,
+            None => self.free_lists[zone][order] = node.next,
+        }
+        if let Some(next) = node.next {
+            self.node_mut(next).previous = node.previous;
+        }
+        self.states[block] = FREE;
+    }
+
+    fn node(&self, block: usize) -> &FreeNode {
+        let address = self.physical_memory_offset + block as u64 * FRAME_SIZE;
+        unsafe { &*address.as_ptr() }
+    }
+
+    fn node_mut(&mut self, block: usize) -> &mut FreeNode {
+        let address = self.physical_memory_offset + block as u64 * FRAME_SIZE;
+        unsafe { &mut *address.as_mut_ptr() }
+    }
+}
+
+unsafe impl FrameAllocator<Size4KiB> for BuddyAllocator {
+    fn allocate_frame(&mut self) -> Option<PhysFrame> {
+        self.allocate(0, Zone::Normal)
+    }
+}
+
+impl FrameDeallocator<Size4KiB> for BuddyAllocator {
+    unsafe fn deallocate_frame(&mut self, frame: PhysFrame) {
+        self.deallocate(frame, 0);
+    }
+}
+
+fn frame_index(frame: PhysFrame) -> usize {
+    (frame.start_address().as_u64() / FRAME_SIZE) as usize
+}
+
+fn frame_at(index: usize) -> PhysFrame {
+    PhysFrame::containing_address(PhysAddr::new(index as u64 * FRAME_SIZE))
+}
+
+#[test_case]
+fn test_order_for() {
+    assert_eq!(order_for(1), 0);
+    assert_eq!(order_for(4096), 0);
+    assert_eq!(order_for(4097), 1);
+    assert_eq!(order_for(2 << 20), 9);
+}
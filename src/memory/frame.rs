@@ -0,0 +1,64 @@
+use super::buddy::{BuddyAllocator, Zone};
+use crate::sync::IrqSafeMutex;
+use bootloader::bootinfo::MemoryMap;
+use x86_64::{
+    structures::paging::{FrameAllocator, FrameDeallocator, PhysFrame, Size4KiB},
+    VirtAddr,
+};
+
+static FRAME_ALLOCATOR: IrqSafeMutex<Option<BuddyAllocator>> = IrqSafeMutex::new(None);
+
+/// Builds the global frame allocator from the memory map, used through `GlobalFrameAllocator`.
+///
+/// This function is unsafe as it is up to the caller to ensure that the complete physical
+/// memory is mapped at `physical_memory_offset` and that all frames marked as `USABLE` in
+/// the memory map are actually unused.
+pub unsafe fn init(memory_map: &'static MemoryMap, physical_memory_offset: VirtAddr) {
+    let allocator = BuddyAllocator::new(memory_map, physical_memory_offset);
+    *FRAME_ALLOCATOR.lock() = Some(allocator);
+}
+
+///
+/// Runs `f` with the global frame allocator, if it has been initialized. <br>
+/// Interrupts are disabled for the duration to prevent deadlocks.
+///
+pub fn with_allocator<R>(f: impl FnOnce(&mut BuddyAllocator) -> R) -> Option<R> {
+    FRAME_ALLOCATOR.lock().as_mut().map(f)
+}
+
+///
+/// Allocates `2^order` physically contiguous frames aligned to their size from `zone`
+/// or below, e.g. for DMA buffers.
+///
+pub fn allocate_contiguous(order: usize, zone: Zone) -> Option<PhysFrame> {
+    with_allocator(|allocator| allocator.allocate(order, zone)).flatten()
+}
+
+/// Frees frames returned by `allocate_contiguous` with the same `order`.
+///
+/// This function is unsafe as it is up to the caller to ensure that the frames are no
+/// longer in use.
+pub unsafe fn deallocate_contiguous(frame: PhysFrame, order: usize) {
+    with_allocator(|allocator| allocator.deallocate(frame, order))
+        .expect("Frame allocator not initialized.");
+}
+
+///
+/// Handle to the global frame allocator set up by `init`, for APIs taking a `FrameAllocator`. <br>
+/// Allocation fails until `init` has been called.
+///
+#[derive(Debug, Clone, Copy, Default)]
+pub struct GlobalFrameAllocator;
+
+unsafe impl FrameAllocator<Size4KiB> for GlobalFrameAllocator {
+    fn allocate_frame(&mut self) -> Option<PhysFrame> {
+        with_allocator(|allocator| allocator.allocate_frame()).flatten()
+    }
+}
+
+impl FrameDeallocator<Size4KiB> for GlobalFrameAllocator {
+    unsafe fn deallocate_frame(&mut self, frame: PhysFrame) {
+        with_allocator(|allocator| allocator.deallocate_frame(frame))
+            .expect("Frame allocator not initialized.");
+    }
+}
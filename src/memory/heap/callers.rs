@@ -0,0 +1,68 @@
+use core::{arch::asm, fmt, mem};
+
+const MAX_FRAME_SIZE: usize = 1024 * 1024; // Larger steps end the frame pointer walk
+
+///
+/// Return addresses of the innermost `N` stack frames, innermost first. <br>
+/// Resolve them with e.g. `addr2line -e <kernel binary>`.
+/// The leak tracker captures them in every build, so the target spec enables frame pointers
+/// (`"frame-pointer": "always"`) for all builds, not only those with `heap-debug`. This costs
+/// `rbp` as a general purpose register and a push and pop per call.
+///
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Callers<const N: usize>([usize; N]);
+
+impl<const N: usize> Callers<N> {
+    pub(super) const EMPTY: Self = Callers([0; N]);
+
+    ///
+    /// Walks the frame pointer chain, which the target keeps in every function. <br>
+    /// The walk ends at a null, misaligned or implausible frame pointer.
+    ///
+    #[inline(always)]
+    pub(super) fn capture() -> Self {
+        let mut callers = [0; N];
+        let mut frame: usize;
+        unsafe { asm!("mov {}, rbp", out(reg) frame, options(nomem, nostack)) };
+
+        for caller in callers.iter_mut() {
+            if frame == 0 || frame % mem::align_of::<usize>() != 0 {
+                break;
+            }
+
+            let (next, return_address) = unsafe {
+                let frame = frame as *const usize;
+                (*frame, *frame.add(1))
+            };
+            *caller = return_address;
+
+            // Callers' frames lie further up the stack.
+            if next <= frame || next - frame > MAX_FRAME_SIZE {
+                break;
+            }
+            frame = next;
+        }
+        Callers(callers)
+    }
+
+    /// Returns the recorded return addresses, innermost first.
+    pub fn addresses(&self) -> &[usize] {
+        let len = self.0.iter().take_while(|&&address| address != 0).count();
+        &self.0[..len]
+    }
+}
+
+impl<const N: usize> fmt::Display for Callers<N> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let (first, rest) = match self.addresses().split_first() {
+            Some(split) => split,
+            None => return write!(f, "<unknown>"),
+        };
+
+        write!(f, "{:#x}", first)?;
+        for address in rest {
+            write!(f, " <- {:#x}", address)?;
+        }
+        Ok(())
+    }
+}
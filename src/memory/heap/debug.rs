@@ -0,0 +1,147 @@
+use super::{align_up, callers::Callers, list_index};
+use alloc::alloc::Layout;
+use core::{
+    fmt, mem,
+    ptr::{self, null_mut},
+    slice,
+};
+
+// Each allocation is laid out as
+// [scratch | padding | header | red zone | data | red zone],
+// where the scratch space is left to the allocator's free list links once freed.
+const SCRATCH: usize = 16;
+const RED_ZONE: usize = 16;
+const CALLERS: usize = 6; // Return addresses recorded per allocation and free
+
+const RED_ZONE_BYTE: u8 = 0xfd;
+const ALLOC_POISON: u8 = 0xa5; // Fresh allocations, exposing reads of uninitialized memory
+const FREE_POISON: u8 = 0xdd; // Freed allocations, exposing use after free
+
+const ALLOCATED: u64 = 0xa110_ca7e_da11_0c8d;
+const FREED: u64 = 0xf4ee_d0d0_f4ee_d0d0;
+
+#[repr(C)]
+struct Header {
+    magic: u64,
+    size: usize,
+    align: usize,
+    allocated_by: Callers<CALLERS>,
+    freed_by: Callers<CALLERS>,
+}
+
+///
+/// Allocates `layout` through `alloc_raw`, surrounded by red zones and prefixed by a header
+/// recording the allocation site. <br>
+/// Blocks served from a block class are checked for writes after their previous free.
+///
+pub(super) unsafe fn alloc(layout: Layout, alloc_raw: impl FnOnce(Layout) -> *mut u8) -> *mut u8 {
+    let (outer, offset) = match outer_layout(&layout) {
+        Some(outer) => outer,
+        None => return null_mut(),
+    };
+    let start = alloc_raw(outer);
+    if start.is_null() {
+        return start;
+    }
+
+    let data = start.add(offset);
+    let header = header_of(data);
+
+    // Block classes always reuse a block with the same geometry, so a previous free at this
+    // offset left the whole span poisoned. Fallback holes are split and merged too freely.
+    if list_index(&outer).is_some() && (*header).magic == FREED {
+        let span = (*header).size.min(layout.size()) + 2 * RED_ZONE;
+        if !is_filled(data.sub(RED_ZONE), span, FREE_POISON) {
+            report("Use after free", data, &*header);
+        }
+    }
+
+    ptr::write_bytes(start, 0, offset - RED_ZONE);
+    header.write(Header {
+        magic: ALLOCATED,
+        size: layout.size(),
+        align: layout.align(),
+        allocated_by: Callers::capture(),
+        freed_by: Callers::EMPTY,
+    });
+    ptr::write_bytes(data.sub(RED_ZONE), RED_ZONE_BYTE, RED_ZONE);
+    ptr::write_bytes(data, ALLOC_POISON, layout.size());
+    ptr::write_bytes(data.add(layout.size()), RED_ZONE_BYTE, RED_ZONE);
+
+    data
+}
+
+///
+/// Checks the header and red zones of the allocation at `data`, then poisons and frees it
+/// through `dealloc_raw`. <br>
+/// Panics, naming the allocation site, on double frees, `Layout` mismatches and overflows.
+///
+pub(super) unsafe fn dealloc(
+    data: *mut u8,
+    layout: Layout,
+    dealloc_raw: impl FnOnce(*mut u8, Layout),
+) {
+    let header = &mut *header_of(data);
+
+    match header.magic {
+        ALLOCATED => {}
+        FREED => report("Double free", data, header),
+        _ => panic!(
+            "Heap corruption: free of {:p} with {:?}, which has no valid allocation header.",
+            data, layout
+        ),
+    }
+    if header.size != layout.size() || header.align != layout.align() {
+        report(
+            format_args!("Free with mismatched {:?}", layout),
+            data,
+            header,
+        );
+    }
+    if !is_filled(data.sub(RED_ZONE), RED_ZONE, RED_ZONE_BYTE) {
+        report("Buffer underflow", data, header);
+    }
+    if !is_filled(data.add(header.size), RED_ZONE, RED_ZONE_BYTE) {
+        report("Buffer overflow", data, header);
+    }
+
+    header.magic = FREED;
+    header.freed_by = Callers::capture();
+    ptr::write_bytes(data.sub(RED_ZONE), FREE_POISON, header.size + 2 * RED_ZONE);
+
+    let (outer, offset) = outer_layout(&layout).unwrap();
+    dealloc_raw(data.sub(offset), outer);
+}
+
+/// Returns the layout including header and red zones, and the offset of the data within it.
+fn outer_layout(layout: &Layout) -> Option<(Layout, usize)> {
+    let align = layout.align().max(mem::align_of::<Header>());
+    let offset = align_up(SCRATCH + mem::size_of::<Header>() + RED_ZONE, align);
+    let size = offset.checked_add(layout.size())?.checked_add(RED_ZONE)?;
+
+    Some((Layout::from_size_align(size, align).ok()?, offset))
+}
+
+fn header_of(data: *mut u8) -> *mut Header {
+    unsafe { data.sub(RED_ZONE + mem::size_of::<Header>()) as *mut Header }
+}
+
+fn is_filled(start: *const u8, len: usize, byte: u8) -> bool {
+    unsafe { slice::from_raw_parts(start, len) }
+        .iter()
+        .all(|&b| b == byte)
+}
+
+fn report(kind: impl fmt::Display, data: *mut u8, header: &Header) -> ! {
+    if header.magic != FREED {
+        panic!(
+            "Heap corruption: {} of {:p} ({} bytes), allocated at {}.",
+            kind, data, header.size, header.allocated_by
+        );
+    }
+
+    panic!(
+        "Heap corruption: {} of {:p} ({} bytes), allocated at {} and freed at {}.",
+        kind, data, header.size, header.allocated_by, header.freed_by
+    );
+}
@@ -0,0 +1,468 @@
+pub mod callers;
+#[cfg(feature = "heap-debug")]
+mod debug;
+pub mod tracker;
+
+use super::{
+    address_space::{self, Permissions},
+    slab::{align_up, SlabList},
+    vmalloc,
+};
+use crate::sync::{IrqSafeMutex, IrqSafeMutexGuard};
+use alloc::alloc::{GlobalAlloc, Layout};
+use core::{
+    fmt,
+    ptr::{null_mut, NonNull},
+    sync::atomic::{AtomicUsize, Ordering},
+};
+use x86_64::{
+    structures::paging::{
+        mapper::MapToError, FrameAllocator, Mapper, Page, PageTableFlags as PTFlags, Size4KiB,
+    },
+    VirtAddr,
+};
+
+pub const HEAP_SIZE: usize = 100 * 1024; // 100KiB, mapped by `init_heap`
+/// Size of the address space `init_heap` reserves for the heap, which it never grows beyond.
+pub const HEAP_MAX_SIZE: usize = 1024 * 1024 * 1024; // 1GiB
+const DEFAULT_HEAP_LIMIT: usize = 64 * 1024 * 1024; // 64MiB
+const HEAP_GROWTH_STEP: usize = 64 * 1024; // Smallest amount mapped at once when growing
+const PAGE_SIZE: usize = 4096;
+const HUGE_PAGE_SIZE: usize = 2 * 1024 * 1024;
+const BLOCK_SIZES: &[usize] = &[8, 16, 32, 64, 128, 256, 512, 1024, 2048];
+
+static HEAP_LIMIT: AtomicUsize = AtomicUsize::new(DEFAULT_HEAP_LIMIT);
+
+struct Dummy;
+
+///
+/// Serves small allocations from per-class slabs, larger ones from the fallback allocator. <br>
+/// Slabs are carved from the fallback allocator and returned to it once fully free,
+/// keeping at most one empty slab per class until `shrink` is called.
+///
+struct FixedSizeBlockAllocator {
+    classes: [SlabList; BLOCK_SIZES.len()], // Slabs of each `BLOCK_SIZES` class
+    fallback_allocator: linked_list_allocator::Heap,
+    allocated: usize, // Bytes handed out, rounded up to the block size
+    high_water_mark: usize,
+    allocations: u64,
+    deallocations: u64,
+}
+
+///
+/// Snapshot of the heap's usage, returned by `stats`. <br>
+/// Its `Display` implementation prints a `free`-like summary.
+///
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HeapStats {
+    pub size: usize,            // Bytes mapped for the heap
+    pub allocated: usize,       // Bytes in use, rounded up to the block size
+    pub free: usize,            // Bytes available, including cached blocks
+    pub high_water_mark: usize, // Most bytes ever in use at once
+    pub allocations: u64,
+    pub deallocations: u64,
+    pub free_blocks: [usize; BLOCK_SIZES.len()], // Cached blocks per `BLOCK_SIZES` class
+    pub slabs: [usize; BLOCK_SIZES.len()],
+    pub fallback_used: usize, // Bytes taken from the fallback allocator, including cached blocks
+    pub fallback_free: usize,
+}
+
+/// Wrapper around IrqSafeMutex to allow trait implementations
+struct Locked<T> {
+    inner: IrqSafeMutex<T>,
+}
+
+unsafe impl GlobalAlloc for Dummy {
+    unsafe fn alloc(&self, _layout: Layout) -> *mut u8 {
+        null_mut()
+    }
+
+    unsafe fn dealloc(&self, _ptr: *mut u8, _layout: Layout) {
+        panic!("dealloc should never be called.")
+    }
+}
+
+impl FixedSizeBlockAllocator {
+    pub const fn new() -> Self {
+        FixedSizeBlockAllocator {
+            classes: block_classes(),
+            fallback_allocator: linked_list_allocator::Heap::empty(),
+            allocated: 0,
+            high_water_mark: 0,
+            allocations: 0,
+            deallocations: 0,
+        }
+    }
+
+    fn fallback_alloc(&mut self, layout: Layout) -> *mut u8 {
+        if let Ok(ptr) = self.fallback_allocator.allocate_first_fit(layout) {
+            return ptr.as_ptr();
+        }
+
+        // Out of memory -> release empty slabs or map more pages at the top of the heap and retry.
+        if self.shrink() > 0 {
+            if let Ok(ptr) = self.fallback_allocator.allocate_first_fit(layout) {
+                return ptr.as_ptr();
+            }
+        }
+        if !self.grow(&layout) {
+            return null_mut();
+        }
+        match self.fallback_allocator.allocate_first_fit(layout) {
+            Ok(ptr) => ptr.as_ptr(),
+            Err(_) => null_mut(),
+        }
+    }
+
+    fn alloc_block(&mut self, index: usize) -> *mut u8 {
+        if let Some(block) = self.classes[index].alloc() {
+            return block.as_ptr();
+        }
+
+        // Carve a new slab from the fallback allocator.
+        let layout = self.classes[index].slab_layout();
+        let start = match NonNull::new(self.fallback_alloc(layout)) {
+            Some(start) => start,
+            None => return null_mut(),
+        };
+        // Stale headers of earlier fallback allocations must not be mistaken for freed blocks.
+        #[cfg(feature = "heap-debug")]
+        unsafe {
+            core::ptr::write_bytes(start.as_ptr(), 0, layout.size());
+        }
+
+        unsafe { self.classes[index].add_slab(start) };
+        self.classes[index]
+            .alloc()
+            .map_or(null_mut(), NonNull::as_ptr)
+    }
+
+    ///
+    /// Puts the block at `ptr` back into its slab. <br>
+    /// This function is unsafe as it is up to the caller to ensure that `ptr` is a block of class `index`.
+    ///
+    unsafe fn dealloc_block(&mut self, index: usize, ptr: *mut u8) {
+        let class = &mut self.classes[index];
+        if let Some(slab) = class.free(NonNull::new_unchecked(ptr)) {
+            self.fallback_allocator
+                .deallocate(slab, class.slab_layout());
+        }
+    }
+
+    /// Releases all empty slabs, returning the number of bytes given back to the fallback allocator.
+    fn shrink(&mut self) -> usize {
+        let fallback_allocator = &mut self.fallback_allocator;
+        self.classes
+            .iter_mut()
+            .map(|class| {
+                let layout = class.slab_layout();
+                class.shrink(|slab| unsafe { fallback_allocator.deallocate(slab, layout) })
+            })
+            .sum()
+    }
+
+    ///
+    /// Maps enough pages past the top of the heap to fit `layout` and hands them to the fallback allocator. <br>
+    /// Fails if the heap limit would be exceeded or the kernel address space and frame allocator are
+    /// unavailable, e.g. when the heap was set up with other ones or the address space is in use.
+    ///
+    fn grow(&mut self, layout: &Layout) -> bool {
+        let size = self.fallback_allocator.size();
+        if size == 0 {
+            return false; // Not initialized
+        }
+
+        let top = self.fallback_allocator.bottom() + size;
+        let mut increment = align_up(
+            (layout.size() + layout.align()).max(HEAP_GROWTH_STEP),
+            PAGE_SIZE,
+        );
+        if size + increment > limit() {
+            return false;
+        }
+
+        // Growing up to a huge page boundary lets large increments be mapped by huge pages.
+        let huge_increment = align_up(top + increment, HUGE_PAGE_SIZE) - top;
+        if increment >= HUGE_PAGE_SIZE && size + huge_increment <= limit() {
+            increment = huge_increment;
+        }
+
+        let top = VirtAddr::new(top as u64);
+        let mapped = address_space::try_with(|space| {
+            space.map_range(top, increment, Permissions::READ_WRITE)
+        });
+
+        match mapped {
+            Some(Ok(())) => {
+                unsafe { self.fallback_allocator.extend(increment) };
+                true
+            }
+            _ => false,
+        }
+    }
+
+    pub unsafe fn init(&mut self, heap_start: usize, heap_size: usize) {
+        self.fallback_allocator.init(heap_start, heap_size);
+    }
+
+    fn record_alloc(&mut self, size: usize) {
+        self.allocated += size;
+        self.high_water_mark = self.high_water_mark.max(self.allocated);
+        self.allocations += 1;
+    }
+
+    fn record_dealloc(&mut self, size: usize) {
+        self.allocated -= size;
+        self.deallocations += 1;
+    }
+
+    fn stats(&self) -> HeapStats {
+        let mut free_blocks = [0; BLOCK_SIZES.len()];
+        let mut slabs = [0; BLOCK_SIZES.len()];
+        for (index, class) in self.classes.iter().enumerate() {
+            free_blocks[index] = class.free_objects();
+            slabs[index] = class.slabs();
+        }
+
+        let size = self.fallback_allocator.size();
+        HeapStats {
+            size,
+            allocated: self.allocated,
+            free: size - self.allocated,
+            high_water_mark: self.high_water_mark,
+            allocations: self.allocations,
+            deallocations: self.deallocations,
+            free_blocks,
+            slabs,
+            fallback_used: self.fallback_allocator.used(),
+            fallback_free: self.fallback_allocator.free(),
+        }
+    }
+}
+
+impl HeapStats {
+    /// Returns the bytes held by cached blocks, which only serve allocations of their class.
+    pub fn cached(&self) -> usize {
+        self.free_blocks
+            .iter()
+            .zip(BLOCK_SIZES)
+            .map(|(count, size)| count * size)
+            .sum()
+    }
+
+    ///
+    /// Estimates fragmentation as the percentage of free memory held by cached blocks. <br>
+    /// That memory cannot serve allocations larger than its block size.
+    ///
+    pub fn fragmentation(&self) -> usize {
+        match self.free {
+            0 => 0,
+            free => self.cached() * 100 / free,
+        }
+    }
+}
+
+impl fmt::Display for HeapStats {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(
+            f,
+            "{:<10}{:>10}{:>10}{:>10}{:>10}{:>10}",
+            "", "total", "used", "free", "cached", "peak"
+        )?;
+        writeln!(
+            f,
+            "{:<10}{:>10}{:>10}{:>10}{:>10}{:>10}",
+            "Heap:",
+            self.size,
+            self.allocated,
+            self.free,
+            self.cached(),
+            self.high_water_mark
+        )?;
+        writeln!(
+            f,
+            "{:<10}{:>10}{:>10}{:>10}",
+            "Fallback:", self.size, self.fallback_used, self.fallback_free
+        )?;
+        write!(
+            f,
+            "{} allocations, {} deallocations, {}% fragmented",
+            self.allocations,
+            self.deallocations,
+            self.fragmentation()
+        )?;
+
+        for (index, size) in BLOCK_SIZES.iter().enumerate() {
+            write!(
+                f,
+                "\n{:>6} B blocks: {} free in {} slabs",
+                size, self.free_blocks[index], self.slabs[index]
+            )?;
+        }
+        Ok(())
+    }
+}
+
+unsafe impl GlobalAlloc for Locked<FixedSizeBlockAllocator> {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        #[cfg(not(feature = "heap-debug"))]
+        let ptr = self.alloc_raw(layout);
+        #[cfg(feature = "heap-debug")]
+        let ptr = debug::alloc(layout, |layout| self.alloc_raw(layout));
+
+        if !ptr.is_null() {
+            tracker::record(ptr, layout);
+        }
+        ptr
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        tracker::forget(ptr);
+
+        #[cfg(not(feature = "heap-debug"))]
+        self.dealloc_raw(ptr, layout);
+        #[cfg(feature = "heap-debug")]
+        debug::dealloc(ptr, layout, |ptr, layout| self.dealloc_raw(ptr, layout));
+    }
+}
+
+impl Locked<FixedSizeBlockAllocator> {
+    fn alloc_raw(&self, layout: Layout) -> *mut u8 {
+        let mut allocator = self.lock();
+        let ptr = match list_index(&layout) {
+            Some(index) => allocator.alloc_block(index),
+            None => allocator.fallback_alloc(layout),
+        };
+
+        if !ptr.is_null() {
+            allocator.record_alloc(footprint(&layout));
+        }
+        ptr
+    }
+
+    unsafe fn dealloc_raw(&self, ptr: *mut u8, layout: Layout) {
+        let mut allocator = self.lock();
+        allocator.record_dealloc(footprint(&layout));
+
+        match list_index(&layout) {
+            Some(index) => allocator.dealloc_block(index, ptr),
+            None => {
+                let ptr = NonNull::new(ptr).unwrap();
+                allocator.fallback_allocator.deallocate(ptr, layout);
+            }
+        }
+    }
+}
+
+impl<T> Locked<T> {
+    pub const fn new(inner: T) -> Self {
+        Locked {
+            inner: IrqSafeMutex::new(inner),
+        }
+    }
+
+    pub fn lock(&self) -> IrqSafeMutexGuard<T> {
+        self.inner.lock()
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: Locked<FixedSizeBlockAllocator> = Locked::new(FixedSizeBlockAllocator::new());
+
+///
+/// Reserves `HEAP_MAX_SIZE` bytes of address space for the heap, maps the first `HEAP_SIZE`
+/// bytes and initializes the allocator. <br>
+/// The heap grows beyond that on demand, up to `limit()`, if `address_space::init` and
+/// `frame::init` have set up the kernel address space and frame allocator.
+///
+pub fn init_heap(
+    mapper: &mut impl Mapper<Size4KiB>,
+    frame_allocator: &mut impl FrameAllocator<Size4KiB>,
+) -> Result<(), MapToError<Size4KiB>> {
+    // Reserving only takes the area table's lock, never the address space's.
+    let heap_start = vmalloc::reserve(HEAP_MAX_SIZE).expect("No address space left for the heap.");
+
+    let page_range = {
+        let heap_start_page = Page::containing_address(heap_start);
+
+        let heap_end = heap_start + HEAP_SIZE - 1_u64;
+        let heap_end_page = Page::containing_address(heap_end);
+
+        Page::range_inclusive(heap_start_page, heap_end_page)
+    };
+
+    for page in page_range {
+        let frame = frame_allocator
+            .allocate_frame()
+            .ok_or(MapToError::FrameAllocationFailed)?;
+        let flags = Permissions::READ_WRITE.flags();
+
+        unsafe {
+            mapper.map_to(page, frame, flags, frame_allocator)?.flush();
+        };
+    }
+
+    unsafe {
+        ALLOCATOR
+            .lock()
+            .init(heap_start.as_u64() as usize, HEAP_SIZE);
+    }
+
+    Ok(())
+}
+
+/// Returns the size the heap may grow to.
+pub fn limit() -> usize {
+    HEAP_LIMIT.load(Ordering::SeqCst)
+}
+
+/// Sets the size the heap may grow to, at most `HEAP_MAX_SIZE`. Memory already mapped is kept.
+pub fn set_limit(limit: usize) {
+    HEAP_LIMIT.store(limit.min(HEAP_MAX_SIZE), Ordering::SeqCst);
+}
+
+/// Returns the number of bytes currently mapped for the heap.
+pub fn size() -> usize {
+    ALLOCATOR.lock().fallback_allocator.size()
+}
+
+///
+/// Returns empty slabs to the fallback allocator, e.g. under memory pressure. <br>
+/// Returns the number of bytes released. Allocations that would fail do this automatically.
+///
+pub fn shrink() -> usize {
+    ALLOCATOR.lock().shrink()
+}
+
+/// Returns a snapshot of the heap's usage.
+pub fn stats() -> HeapStats {
+    ALLOCATOR.lock().stats()
+}
+
+fn list_index(layout: &Layout) -> Option<usize> {
+    let required_block_size = layout.size().max(layout.align());
+
+    BLOCK_SIZES.iter().position(|&s| s >= required_block_size)
+}
+
+///
+/// Creates the slab lists of the block classes. <br>
+/// The slab header takes up the first block, so each slab holds at least seven blocks.
+///
+const fn block_classes() -> [SlabList; BLOCK_SIZES.len()] {
+    const EMPTY: SlabList = SlabList::new(0, 1, 0);
+    let mut classes = [EMPTY; BLOCK_SIZES.len()];
+
+    let mut index = 0;
+    while index < BLOCK_SIZES.len() {
+        let block_size = BLOCK_SIZES[index];
+        classes[index] = SlabList::new(block_size, block_size, 7);
+        index += 1;
+    }
+    classes
+}
+
+/// Returns the bytes an allocation of `layout` takes up, i.e. its block size if it has a class.
+fn footprint(layout: &Layout) -> usize {
+    list_index(layout).map_or(layout.size(), |index| BLOCK_SIZES[index])
+}
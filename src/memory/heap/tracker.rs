@@ -0,0 +1,175 @@
+use super::callers::Callers;
+use crate::{serial_println, sync::IrqSafeMutex};
+use alloc::alloc::Layout;
+use core::{
+    fmt,
+    sync::atomic::{AtomicBool, Ordering},
+};
+
+const MAX_TRACKED: usize = 1024; // Live allocations recorded at once
+const CALLERS: usize = 4;
+
+static TRACKING: AtomicBool = AtomicBool::new(false);
+static TRACKER: IrqSafeMutex<Tracker> = IrqSafeMutex::new(Tracker::new());
+
+/// A live allocation recorded by the tracker.
+#[derive(Debug, Clone, Copy)]
+pub struct Allocation {
+    pub address: usize,
+    pub layout: Layout,
+    pub callers: Callers<CALLERS>,
+    sequence: u64,
+}
+
+/// Live allocations, kept in a fixed table since the tracker itself cannot allocate.
+struct Tracker {
+    allocations: [Option<Allocation>; MAX_TRACKED],
+    next_sequence: u64,
+    untracked: u64, // Allocations dropped because the table was full
+    snapshots: usize,
+}
+
+///
+/// Marks a point to check for leaks from, returned by `snapshot`. <br>
+/// Allocations are tracked while any snapshot is alive.
+///
+pub struct Snapshot {
+    sequence: u64,
+    untracked: u64,
+}
+
+impl Tracker {
+    const fn new() -> Self {
+        Tracker {
+            allocations: [None; MAX_TRACKED],
+            next_sequence: 0,
+            untracked: 0,
+            snapshots: 0,
+        }
+    }
+}
+
+///
+/// Starts tracking allocations, if not already, and returns a snapshot of the current point. <br>
+/// Only allocations made after the snapshot are reported by it.
+///
+pub fn snapshot() -> Snapshot {
+    let mut tracker = TRACKER.lock();
+    tracker.snapshots += 1;
+    TRACKING.store(true, Ordering::SeqCst);
+
+    Snapshot {
+        sequence: tracker.next_sequence,
+        untracked: tracker.untracked,
+    }
+}
+
+/// Records the allocation of `ptr` with `layout` while tracking.
+#[inline(always)]
+pub(super) fn record(ptr: *mut u8, layout: Layout) {
+    if !TRACKING.load(Ordering::Relaxed) {
+        return;
+    }
+
+    let callers = Callers::capture();
+    let mut tracker = TRACKER.lock();
+    let sequence = tracker.next_sequence;
+    tracker.next_sequence += 1;
+
+    match tracker.allocations.iter_mut().find(|slot| slot.is_none()) {
+        Some(slot) => {
+            *slot = Some(Allocation {
+                address: ptr as usize,
+                layout,
+                callers,
+                sequence,
+            })
+        }
+        None => tracker.untracked += 1,
+    }
+}
+
+/// Forgets the allocation of `ptr` while tracking.
+pub(super) fn forget(ptr: *mut u8) {
+    if !TRACKING.load(Ordering::Relaxed) {
+        return;
+    }
+
+    let mut tracker = TRACKER.lock();
+    let address = ptr as usize;
+    if let Some(slot) = tracker
+        .allocations
+        .iter_mut()
+        .find(|slot| matches!(slot, Some(allocation) if allocation.address == address))
+    {
+        *slot = None;
+    }
+}
+
+impl Snapshot {
+    ///
+    /// Runs `f` for each allocation made since the snapshot that is still live. <br>
+    /// `f` must not allocate on the heap, as the tracker is locked meanwhile.
+    ///
+    pub fn for_each_leak(&self, f: impl FnMut(&Allocation)) {
+        let tracker = TRACKER.lock();
+        tracker
+            .allocations
+            .iter()
+            .flatten()
+            .filter(|allocation| allocation.sequence >= self.sequence)
+            .for_each(f);
+    }
+
+    /// Returns the number of allocations made since the snapshot that are still live.
+    pub fn leak_count(&self) -> usize {
+        let mut count = 0;
+        self.for_each_leak(|_| count += 1);
+        count
+    }
+
+    ///
+    /// Prints the allocations made since the snapshot that are still live to serial. <br>
+    /// Panics if there are any, or if allocations could not be tracked.
+    ///
+    pub fn assert_no_leaks(&self) {
+        let mut count = 0;
+        self.for_each_leak(|allocation| {
+            serial_println!("Leaked {}", allocation);
+            count += 1;
+        });
+
+        let untracked = TRACKER.lock().untracked - self.untracked;
+        assert!(
+            count == 0 && untracked == 0,
+            "{} allocations leaked, {} more could not be tracked.",
+            count,
+            untracked
+        );
+    }
+}
+
+impl Drop for Snapshot {
+    fn drop(&mut self) {
+        let mut tracker = TRACKER.lock();
+        tracker.snapshots -= 1;
+
+        if tracker.snapshots == 0 {
+            TRACKING.store(false, Ordering::SeqCst);
+            tracker.allocations = [None; MAX_TRACKED];
+        }
+    }
+}
+
+impl fmt::Display for Allocation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{:#x} ({} bytes, align {}), allocated at {}",
+            self.address,
+            self.layout.size(),
+            self.layout.align(),
+            self.callers
+        )
+    }
+}
@@ -0,0 +1,118 @@
+use super::{
+    address_space::{self, Permissions},
+    paging,
+};
+use crate::sync::IrqSafeMutex;
+use core::ptr;
+use x86_64::VirtAddr;
+
+const MAX_REGIONS: usize = 64;
+const PAGE_SIZE: u64 = 4096;
+
+/// Registered regions, kept in a fixed table so that registering never allocates, e.g. while growing the heap.
+static REGIONS: IrqSafeMutex<[Option<Region>; MAX_REGIONS]> =
+    IrqSafeMutex::new([None; MAX_REGIONS]);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LazyError {
+    TooManyRegions,
+    Overlapping, // Part of the range belongs to a registered region already
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Region {
+    start: u64,
+    end: u64,
+    permissions: Permissions,
+}
+
+///
+/// Registers the pages covering `size` bytes from `start` as a lazy region: each page is backed
+/// by a zeroed frame mapped with `permissions` when first touched. <br>
+/// The range must be reserved address space, e.g. from `vmalloc::reserve`. Pages mapped by
+/// the handler stay mapped after `unregister`, so the owner unmaps them with `unmap_range`.
+///
+pub fn register(start: VirtAddr, size: usize, permissions: Permissions) -> Result<(), LazyError> {
+    let region = Region {
+        start: start.align_down(PAGE_SIZE).as_u64(),
+        end: (start + size.max(1) as u64).align_up(PAGE_SIZE).as_u64(),
+        permissions,
+    };
+
+    let mut regions = REGIONS.lock();
+    let overlapping = regions
+        .iter()
+        .flatten()
+        .any(|other| region.start < other.end && other.start < region.end);
+    if overlapping {
+        return Err(LazyError::Overlapping);
+    }
+
+    let slot = regions
+        .iter_mut()
+        .find(|slot| slot.is_none())
+        .ok_or(LazyError::TooManyRegions)?;
+    *slot = Some(region);
+    Ok(())
+}
+
+/// Removes the lazy region containing `address`, returning `false` if there is none.
+pub fn unregister(address: VirtAddr) -> bool {
+    let mut regions = REGIONS.lock();
+    let slot = regions
+        .iter_mut()
+        .find(|slot| matches!(slot, Some(region) if region.contains(address)));
+
+    match slot {
+        Some(slot) => {
+            *slot = None;
+            true
+        }
+        None => false,
+    }
+}
+
+///
+/// Backs the page containing `address` with a zeroed frame if it lies in a lazy region and is
+/// not mapped yet. <br>
+/// Called by the page fault handler, which resumes the faulting code if this returns `true`.
+/// Fails if the registry or address space is locked by the faulting code, or no frame is left.
+///
+pub fn handle_fault(address: VirtAddr) -> bool {
+    let region = match REGIONS.try_lock() {
+        Some(regions) => regions
+            .iter()
+            .flatten()
+            .find(|region| region.contains(address))
+            .copied(),
+        None => return false,
+    };
+    let (region, offset) = match (region, paging::physical_memory_offset()) {
+        (Some(region), Some(offset)) => (region, offset),
+        _ => return false,
+    };
+
+    // A page mapped already faulted on its permissions, which are not this handler's to change.
+    let page = address.align_down(PAGE_SIZE);
+    let mapped = address_space::try_with(|space| {
+        space
+            .map_range(page, PAGE_SIZE as usize, region.permissions)
+            .map(|()| space.translate(page).unwrap())
+    });
+
+    match mapped {
+        Some(Ok(frame)) => {
+            // Zeroed through the physical memory window, as the page may not be writable.
+            let frame = (offset + frame.as_u64()).as_mut_ptr::<u8>();
+            unsafe { ptr::write_bytes(frame, 0, PAGE_SIZE as usize) };
+            true
+        }
+        _ => false,
+    }
+}
+
+impl Region {
+    fn contains(&self, address: VirtAddr) -> bool {
+        (self.start..self.end).contains(&address.as_u64())
+    }
+}
@@ -1,2 +1,10 @@
+pub mod address_space;
+pub mod buddy;
+pub mod frame;
 pub mod heap;
+pub mod lazy;
 pub mod paging;
+pub mod protection;
+pub mod slab;
+pub mod user;
+pub mod vmalloc;
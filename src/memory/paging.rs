@@ -1,13 +1,22 @@
+use crate::console_println;
 use bootloader::bootinfo::{MemoryMap, MemoryRegionType};
+use core::{
+    fmt,
+    sync::atomic::{AtomicU64, Ordering},
+};
 use x86_64::{
     registers::control::Cr3,
     structures::paging::{
-        FrameAllocator, Mapper, OffsetPageTable, Page, PageTable, PageTableFlags as PTFlags,
-        PhysFrame, Size4KiB,
+        FrameAllocator, OffsetPageTable, PageTable, PageTableFlags as PTFlags, PhysFrame, Size4KiB,
     },
     PhysAddr, VirtAddr,
 };
 
+const NO_OFFSET: u64 = u64::MAX;
+
+/// Where physical memory is mapped, remembered by `init_offset_page_table` for the inspection functions.
+static PHYSICAL_MEMORY_OFFSET: AtomicU64 = AtomicU64::new(NO_OFFSET);
+
 /// A FrameAllocator that returns usable frames from the bootloader's memory map.
 #[allow(dead_code)]
 pub struct BootInfoFrameAllocator {
@@ -52,22 +61,6 @@ unsafe impl FrameAllocator<Size4KiB> for BootInfoFrameAllocator {
     }
 }
 
-/// Creates an example mapping for the given page to frame `0xb8000`.
-pub fn create_example_mapping(
-    page: Page,
-    mapper: &mut OffsetPageTable,
-    frame_allocator: &mut impl FrameAllocator<Size4KiB>,
-) {
-    let frame = PhysFrame::containing_address(PhysAddr::new(0xb8000));
-    let flags = PTFlags::PRESENT | PTFlags::WRITABLE;
-
-    let map_to_result = unsafe {
-        // FIXME: do not leave this in istg
-        mapper.map_to(page, frame, flags, frame_allocator)
-    };
-    map_to_result.expect("map_to failed.").flush();
-}
-
 /// Returns a mutable reference to the active level 4 table
 ///
 /// This function is unsafe as it is up to the caller to ensure that the
@@ -75,13 +68,15 @@ pub fn create_example_mapping(
 /// `physical_memory_offset`. Also, this function must only be called
 /// once to avoid aliasing `&mut` references (which is undefined behaviour).
 pub unsafe fn get_active_level_4_table(physical_memory_offset: VirtAddr) -> &'static mut PageTable {
+    &mut *active_level_4_table_ptr(physical_memory_offset)
+}
+
+fn active_level_4_table_ptr(physical_memory_offset: VirtAddr) -> *mut PageTable {
     let (l4_table_frame, _) = Cr3::read();
 
     let phys_addr = l4_table_frame.start_address();
     let virt_addr = physical_memory_offset + phys_addr.as_u64();
-    let page_table_ptr: *mut PageTable = virt_addr.as_mut_ptr();
-
-    &mut *page_table_ptr
+    virt_addr.as_mut_ptr()
 }
 
 /// Initialize a new OffsetPageTable.
@@ -91,6 +86,259 @@ pub unsafe fn get_active_level_4_table(physical_memory_offset: VirtAddr) -> &'st
 /// `physical_memory_offset`. Also, this function must only be called
 /// once to avoid aliasing `&mut` references (which is undefined behaviour).
 pub unsafe fn init_offset_page_table(physical_memory_offset: VirtAddr) -> OffsetPageTable<'static> {
+    PHYSICAL_MEMORY_OFFSET.store(physical_memory_offset.as_u64(), Ordering::SeqCst);
+
     let l4_table = get_active_level_4_table(physical_memory_offset);
     OffsetPageTable::new(l4_table, physical_memory_offset)
 }
+
+///
+/// A run of virtually contiguous pages mapped with the same effective flags. <br>
+/// Writable and user access need every level of the walk to allow them, while one level
+/// denying execution is enough.
+///
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MappedRange {
+    pub start: VirtAddr,
+    pub size: u64, // The end would not be canonical for a range ending a half of the address space
+    pub flags: PTFlags,
+}
+
+/// The page table entries used to translate an address, returned by `translation_path`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TranslationPath {
+    pub address: VirtAddr,
+    pub steps: [Option<TranslationStep>; 4], // Level 4 first, ending at the first leaf or missing entry
+    pub physical: Option<PhysAddr>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TranslationStep {
+    pub level: u8,
+    pub table: PhysAddr,
+    pub index: usize,
+    pub address: PhysAddr, // Next table or mapped memory
+    pub flags: PTFlags,
+}
+
+///
+/// Walks all four levels of the active page tables and runs `f` for each mapped range,
+/// coalesced from adjacent pages with the same effective flags. <br>
+/// Takes no locks and does not allocate, so it also works from the panic path. Does nothing
+/// before `init_offset_page_table`.
+///
+pub fn for_each_mapped_range(mut f: impl FnMut(&MappedRange)) {
+    let offset = match physical_memory_offset() {
+        Some(offset) => offset,
+        None => return,
+    };
+
+    let mut current: Option<MappedRange> = None;
+    let level_4_table = unsafe { &*active_level_4_table_ptr(offset) };
+    let inherited = PTFlags::WRITABLE | PTFlags::USER_ACCESSIBLE;
+
+    walk_table(
+        level_4_table,
+        4,
+        0,
+        inherited,
+        offset,
+        &mut |start, size, flags| match current.as_mut() {
+            Some(range) if range.end() == start.as_u64() && range.flags == flags => {
+                range.size += size
+            }
+            _ => {
+                if let Some(range) = current.replace(MappedRange { start, size, flags }) {
+                    f(&range);
+                }
+            }
+        },
+    );
+
+    if let Some(range) = current {
+        f(&range);
+    }
+}
+
+///
+/// Prints all mapped ranges of the active page tables to virtual console `console`. <br>
+/// Used by the Alt+P shortcut.
+///
+pub fn dump(console: usize) {
+    console_println!(console, "\n{:<37}{:>10}  flags", "mapped range", "size");
+    let mut count = 0;
+    for_each_mapped_range(|range| {
+        console_println!(console, "{}", range);
+        count += 1;
+    });
+    console_println!(console, "{} ranges", count);
+}
+
+///
+/// Looks up the page table entries translating `address` in the active page tables. <br>
+/// Takes no locks and does not allocate, so it also works from the panic path. Returns `None`
+/// before `init_offset_page_table`.
+///
+pub fn translation_path(address: VirtAddr) -> Option<TranslationPath> {
+    let offset = physical_memory_offset()?;
+    let indices = [
+        address.p4_index(),
+        address.p3_index(),
+        address.p2_index(),
+        address.p1_index(),
+    ];
+
+    let mut path = TranslationPath {
+        address,
+        steps: [None; 4],
+        physical: None,
+    };
+    let mut table_address = Cr3::read().0.start_address();
+
+    for (step, index) in indices.iter().enumerate() {
+        let level = 4 - step as u8;
+        let table = unsafe { &*(offset + table_address.as_u64()).as_ptr::<PageTable>() };
+        let entry = &table[*index];
+
+        path.steps[step] = Some(TranslationStep {
+            level,
+            table: table_address,
+            index: usize::from(*index),
+            address: entry.addr(),
+            flags: entry.flags(),
+        });
+        if !entry.flags().contains(PTFlags::PRESENT) {
+            break;
+        }
+
+        if level == 1 || entry.flags().contains(PTFlags::HUGE_PAGE) {
+            let page_offset = address.as_u64() & (page_size(level) - 1);
+            path.physical = Some(entry.addr() + page_offset);
+            break;
+        }
+        table_address = entry.addr();
+    }
+
+    Some(path)
+}
+
+/// Returns the offset passed to `init_offset_page_table`, if it has been called.
+pub fn physical_memory_offset() -> Option<VirtAddr> {
+    match PHYSICAL_MEMORY_OFFSET.load(Ordering::SeqCst) {
+        NO_OFFSET => None,
+        offset => Some(VirtAddr::new(offset)),
+    }
+}
+
+/// Calls `f` with the start, size and effective flags of each page mapped by `table`.
+fn walk_table(
+    table: &PageTable,
+    level: u8,
+    base: u64,
+    inherited: PTFlags,
+    offset: VirtAddr,
+    f: &mut impl FnMut(VirtAddr, u64, PTFlags),
+) {
+    for (index, entry) in table.iter().enumerate() {
+        let flags = entry.flags();
+        if !flags.contains(PTFlags::PRESENT) {
+            continue;
+        }
+
+        let start = base | (index as u64) << (12 + 9 * (level - 1));
+        let effective = (inherited & flags & (PTFlags::WRITABLE | PTFlags::USER_ACCESSIBLE))
+            | ((inherited | flags) & PTFlags::NO_EXECUTE);
+
+        // The huge page bit of level 1 entries selects the memory type instead.
+        if level == 1 || flags.contains(PTFlags::HUGE_PAGE) {
+            let leaf = effective
+                | PTFlags::PRESENT
+                | (flags & PTFlags::GLOBAL)
+                | if level > 1 {
+                    PTFlags::HUGE_PAGE
+                } else {
+                    PTFlags::empty()
+                };
+            f(VirtAddr::new_truncate(start), page_size(level), leaf);
+        } else {
+            let child = unsafe { &*(offset + entry.addr().as_u64()).as_ptr::<PageTable>() };
+            walk_table(child, level - 1, start, effective, offset, f);
+        }
+    }
+}
+
+/// Returns the size of memory mapped by an entry of a table of `level`.
+fn page_size(level: u8) -> u64 {
+    1 << (12 + 9 * (u64::from(level) - 1))
+}
+
+/// Writes the inspected flags, with a dash for each one not set.
+fn fmt_flags(flags: PTFlags, f: &mut fmt::Formatter) -> fmt::Result {
+    let names = [
+        (PTFlags::PRESENT, "P"),
+        (PTFlags::WRITABLE, "W"),
+        (PTFlags::USER_ACCESSIBLE, "U"),
+        (PTFlags::NO_EXECUTE, "NX"),
+        (PTFlags::HUGE_PAGE, "H"),
+        (PTFlags::GLOBAL, "G"),
+    ];
+
+    for (flag, name) in names.iter() {
+        if flags.contains(*flag) {
+            write!(f, " {}", name)?;
+        } else {
+            write!(f, " {:-<1$}", "", name.len())?;
+        }
+    }
+    Ok(())
+}
+
+impl MappedRange {
+    /// Returns the address following the range, which wraps to 0 at the end of the address space.
+    pub fn end(&self) -> u64 {
+        self.start.as_u64().wrapping_add(self.size)
+    }
+}
+
+impl fmt::Display for MappedRange {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let (size, unit) = match self.size {
+            size if size >= 1 << 30 && size % (1 << 30) == 0 => (size >> 30, "GiB"),
+            size if size >= 1 << 20 && size % (1 << 20) == 0 => (size >> 20, "MiB"),
+            size => (size >> 10, "KiB"),
+        };
+
+        write!(
+            f,
+            "{:#018x}-{:#018x}{:>6} {} ",
+            self.start.as_u64(),
+            self.end(),
+            size,
+            unit
+        )?;
+        fmt_flags(self.flags, f)
+    }
+}
+
+impl fmt::Display for TranslationPath {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Translation of {:#x}:", self.address.as_u64())?;
+
+        for step in self.steps.iter().flatten() {
+            write!(
+                f,
+                "\n  P{}[{:>3}] in {:#x} -> {:#x}",
+                step.level,
+                step.index,
+                step.table.as_u64(),
+                step.address.as_u64()
+            )?;
+            fmt_flags(step.flags, f)?;
+        }
+
+        match self.physical {
+            Some(physical) => write!(f, "\n  = {:#x}", physical.as_u64()),
+            None => write!(f, "\n  not mapped"),
+        }
+    }
+}
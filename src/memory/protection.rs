@@ -0,0 +1,198 @@
+use super::{
+    address_space::{self, Permissions},
+    paging::{self, MappedRange},
+};
+use core::{
+    arch::{
+        asm,
+        x86_64::{__cpuid, __cpuid_count},
+    },
+    mem, slice,
+};
+use x86_64::{
+    registers::{
+        control::{Cr0, Cr0Flags, Cr4, Cr4Flags},
+        model_specific::{Efer, EferFlags},
+    },
+    VirtAddr,
+};
+
+const PAGE_SIZE: u64 = 4096;
+const ELF_MAGIC: [u8; 4] = *b"\x7fELF";
+const PT_LOAD: u32 = 1;
+const PF_X: u32 = 1; // Executable segment
+const PF_W: u32 = 2; // Writable segment
+
+extern "C" {
+    /// The kernel's ELF header, which the linker places at the start of the first loaded segment.
+    static __ehdr_start: ElfHeader;
+}
+
+/// The start of an ELF64 file header, up to the program header table fields.
+#[allow(dead_code)]
+#[repr(C)]
+struct ElfHeader {
+    ident: [u8; 16],
+    kind: u16,
+    machine: u16,
+    version: u32,
+    entry: u64,
+    program_headers: u64, // Offset of the program header table from the header
+    section_headers: u64,
+    flags: u32,
+    header_size: u16,
+    program_header_size: u16,
+    program_header_count: u16,
+}
+
+#[allow(dead_code)]
+#[repr(C)]
+struct ProgramHeader {
+    kind: u32,
+    flags: u32,
+    offset: u64,
+    virtual_address: u64,
+    physical_address: u64,
+    file_size: u64,
+    memory_size: u64, // Includes zero-filled memory such as `.bss`
+    align: u64,
+}
+
+///
+/// Makes the CPU enforce page permissions against the kernel itself: `EFER.NXE` honours
+/// no-execute pages and `CR0.WP` faults on kernel writes to read-only pages. <br>
+/// SMEP and SMAP fault on the kernel executing or accessing user pages, the latter outside
+/// the `user` copy helpers, and UMIP keeps user mode from reading descriptor table registers.
+/// Each is only enabled if CPUID reports it. Called by `init`, before any mapping is made.
+///
+pub fn enable() {
+    if has_no_execute() {
+        unsafe { Efer::update(|flags| flags.insert(EferFlags::NO_EXECUTE_ENABLE)) };
+    }
+    unsafe { Cr0::update(|flags| flags.insert(Cr0Flags::WRITE_PROTECT)) };
+
+    let features = supervisor_features();
+    if !features.is_empty() {
+        unsafe { Cr4::update(|flags| flags.insert(features)) };
+    }
+}
+
+/// Remaps the kernel so that no memory is both writable and executable.
+///
+/// The loaded segments found through the kernel's ELF headers become read-execute (`.text`),
+/// read-only (`.rodata`) or read-write (`.data` and `.bss`). The boot stack and the physical
+/// memory window become non-executable, as the heap and other mappings made through
+/// `address_space` already are once `enable` has run.
+///
+/// This function is unsafe as it is up to the caller to ensure that no code writes to the
+/// kernel's code or read-only data, or executes code outside `.text`.
+pub unsafe fn protect_kernel() {
+    let mut previous_end = 0;
+    for segment in loaded_segments() {
+        let start = VirtAddr::new(segment.virtual_address).align_down(PAGE_SIZE);
+        let end = VirtAddr::new(segment.virtual_address + segment.memory_size).align_up(PAGE_SIZE);
+        assert!(
+            start.as_u64() >= previous_end,
+            "Kernel segments at {:#x} share a page.",
+            segment.virtual_address
+        );
+        previous_end = end.as_u64();
+
+        let permissions = if segment.flags & PF_X != 0 {
+            Permissions::READ_EXECUTE
+        } else if segment.flags & PF_W != 0 {
+            Permissions::READ_WRITE
+        } else {
+            Permissions::READ
+        };
+        address_space::with(|space| space.protect(start, (end - start) as usize, permissions))
+            .expect("Failed to protect a kernel segment.");
+    }
+
+    // The boot stack lies between unmapped guard pages, so it is a mapped range of its own.
+    let stack_pointer: u64;
+    asm!("mov {}, rsp", out(reg) stack_pointer, options(nomem, nostack));
+    let stack = mapped_range(|range| (range.start.as_u64()..range.end()).contains(&stack_pointer));
+    protect_read_write(stack.expect("Boot stack not mapped."));
+
+    // The window may consist of several ranges, e.g. where huge and small pages meet.
+    if let Some(offset) = paging::physical_memory_offset() {
+        let mut start = offset.as_u64();
+        while let Some(range) = mapped_range(|range| range.start.as_u64() == start) {
+            protect_read_write(range);
+            start = range.end();
+        }
+    }
+}
+
+/// Returns `true` if the CPU supports no-execute pages.
+pub fn has_no_execute() -> bool {
+    let max_extended_leaf = unsafe { __cpuid(0x8000_0000) }.eax;
+    max_extended_leaf >= 0x8000_0001 && unsafe { __cpuid(0x8000_0001) }.edx & (1 << 20) != 0
+}
+
+/// Returns the SMEP, SMAP and UMIP flags of `CR4` that the CPU supports.
+pub fn supervisor_features() -> Cr4Flags {
+    let mut features = Cr4Flags::empty();
+    if unsafe { __cpuid(0) }.eax < 7 {
+        return features;
+    }
+
+    let extended_features = unsafe { __cpuid_count(7, 0) };
+    features.set(
+        Cr4Flags::SUPERVISOR_MODE_EXECUTION_PROTECTION,
+        extended_features.ebx & (1 << 7) != 0,
+    );
+    features.set(
+        Cr4Flags::SUPERVISOR_MODE_ACCESS_PREVENTION,
+        extended_features.ebx & (1 << 20) != 0,
+    );
+    features.set(
+        Cr4Flags::USER_MODE_INSTRUCTION_PREVENTION,
+        extended_features.ecx & (1 << 2) != 0,
+    );
+    features
+}
+
+/// Returns the kernel's loadable segments, described by the program headers following its ELF header.
+fn loaded_segments() -> impl Iterator<Item = &'static ProgramHeader> {
+    let header = unsafe { &__ehdr_start };
+    assert_eq!(
+        header.ident[..4],
+        ELF_MAGIC,
+        "Kernel ELF header not mapped."
+    );
+    assert_eq!(
+        usize::from(header.program_header_size),
+        mem::size_of::<ProgramHeader>()
+    );
+
+    let program_headers = unsafe {
+        let start = (header as *const ElfHeader as *const u8).add(header.program_headers as usize);
+        slice::from_raw_parts(
+            start as *const ProgramHeader,
+            usize::from(header.program_header_count),
+        )
+    };
+    program_headers
+        .iter()
+        .filter(|program_header| program_header.kind == PT_LOAD)
+}
+
+/// Returns the first mapped range of the active page tables matching `predicate`.
+fn mapped_range(mut predicate: impl FnMut(&MappedRange) -> bool) -> Option<MappedRange> {
+    let mut found = None;
+    paging::for_each_mapped_range(|range| {
+        if found.is_none() && predicate(range) {
+            found = Some(*range);
+        }
+    });
+    found
+}
+
+unsafe fn protect_read_write(range: MappedRange) {
+    address_space::with(|space| {
+        space.protect(range.start, range.size as usize, Permissions::READ_WRITE)
+    })
+    .expect("Failed to make a range non-executable.");
+}
@@ -0,0 +1,416 @@
+use crate::sync::IrqSafeMutex;
+use alloc::alloc::{alloc, dealloc, Layout};
+use core::{fmt, marker::PhantomData, mem, ptr::NonNull};
+
+const PAGE_SIZE: usize = 4096;
+const MIN_OBJECTS_PER_SLAB: usize = 8;
+
+///
+/// A cache of equally sized kernel objects, e.g. tasks or wakers, allocated from slabs. <br>
+/// Slabs are size-aligned chunks taken from the heap, so objects of one type stay together
+/// instead of fragmenting it, and each cache keeps its own statistics. Fully free slabs are
+/// returned to the heap, except for one kept to avoid thrashing until `shrink` is called.
+/// Dropping a cache returns all of its slabs, and panics if any of its objects is still in use.
+///
+pub struct SlabCache<T> {
+    name: &'static str,
+    constructor: Option<fn() -> T>,
+    inner: IrqSafeMutex<RawCache>,
+    _marker: PhantomData<T>,
+}
+
+/// Snapshot of a slab cache's usage, returned by `SlabCache::stats`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SlabCacheStats {
+    pub name: &'static str,
+    pub object_size: usize, // Bytes per object, including padding
+    pub slab_size: usize,
+    pub slabs: usize,
+    pub in_use: usize, // Objects currently allocated
+    pub free: usize,   // Objects available in the cache's slabs
+    pub high_water_mark: usize,
+    pub allocations: u64,
+    pub frees: u64,
+}
+
+/// Untyped state of a cache, guarded by its lock.
+struct RawCache {
+    slabs: SlabList,
+    in_use: usize,
+    high_water_mark: usize,
+    allocations: u64,
+    frees: u64,
+}
+
+///
+/// The slabs holding objects of one size, shared by slab caches and the heap's block classes. <br>
+/// Slabs are `slab_size` aligned, so an object's slab is found by masking its address. The
+/// owner provides the memory of new slabs and takes back those released by `free` and `shrink`.
+///
+pub(super) struct SlabList {
+    object_size: usize,
+    first_object: usize, // Offset of the first object, past the slab header
+    slab_size: usize,
+    partial_slabs: Option<NonNull<Slab>>, // Slabs with free objects
+    slabs: usize,
+    empty_slabs: usize,
+    free: usize,
+}
+
+/// Header at the start of each slab.
+struct Slab {
+    free_list: Option<NonNull<FreeObject>>,
+    free_count: usize,
+    previous: Option<NonNull<Slab>>, // Neighbours in the partial slab list
+    next: Option<NonNull<Slab>>,
+}
+
+struct FreeObject {
+    next: Option<NonNull<FreeObject>>,
+}
+
+// Objects may be allocated on one core and freed on another, which requires `T: Send`.
+unsafe impl<T: Send> Sync for SlabCache<T> {}
+
+// The slabs are only reachable through their owner, e.g. the cache's lock.
+unsafe impl Send for SlabList {}
+
+impl<T> SlabCache<T> {
+    /// Creates an empty cache, whose objects are allocated with `alloc_with`.
+    pub const fn new(name: &'static str) -> Self {
+        Self::build(name, None)
+    }
+
+    /// Creates an empty cache whose `alloc` initializes objects with `constructor`.
+    pub const fn with_constructor(name: &'static str, constructor: fn() -> T) -> Self {
+        Self::build(name, Some(constructor))
+    }
+
+    const fn build(name: &'static str, constructor: Option<fn() -> T>) -> Self {
+        SlabCache {
+            name,
+            constructor,
+            inner: IrqSafeMutex::new(RawCache {
+                slabs: SlabList::new(
+                    mem::size_of::<T>(),
+                    mem::align_of::<T>(),
+                    MIN_OBJECTS_PER_SLAB,
+                ),
+                in_use: 0,
+                high_water_mark: 0,
+                allocations: 0,
+                frees: 0,
+            }),
+            _marker: PhantomData,
+        }
+    }
+
+    /// Returns the name the cache was created with.
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+
+    ///
+    /// Allocates an object initialized by the cache's constructor, or `None` if out of memory. <br>
+    /// Panics if the cache was created without a constructor.
+    ///
+    pub fn alloc(&self) -> Option<NonNull<T>> {
+        let constructor = self
+            .constructor
+            .unwrap_or_else(|| panic!("Slab cache `{}` has no constructor.", self.name));
+        self.alloc_with(constructor())
+    }
+
+    /// Allocates an object holding `value`, or `None` if out of memory.
+    pub fn alloc_with(&self, value: T) -> Option<NonNull<T>> {
+        let object = self.inner.lock().alloc()?.cast::<T>();
+        unsafe { object.as_ptr().write(value) };
+        Some(object)
+    }
+
+    /// Drops an object and returns its memory to the cache.
+    ///
+    /// This function is unsafe as it is up to the caller to ensure that `object` was
+    /// allocated from this cache and is no longer in use.
+    pub unsafe fn free(&self, object: NonNull<T>) {
+        object.as_ptr().drop_in_place();
+        self.inner.lock().free(object.cast());
+    }
+
+    /// Returns empty slabs to the heap, returning the number of bytes released.
+    pub fn shrink(&self) -> usize {
+        self.inner.lock().shrink()
+    }
+
+    /// Returns a snapshot of the cache's usage.
+    pub fn stats(&self) -> SlabCacheStats {
+        let cache = self.inner.lock();
+        SlabCacheStats {
+            name: self.name,
+            object_size: cache.slabs.object_size,
+            slab_size: cache.slabs.slab_size,
+            slabs: cache.slabs.slabs,
+            in_use: cache.in_use,
+            free: cache.slabs.free,
+            high_water_mark: cache.high_water_mark,
+            allocations: cache.allocations,
+            frees: cache.frees,
+        }
+    }
+}
+
+impl fmt::Display for SlabCacheStats {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{:<16} {:>6} B objects: {} in use, {} free, {} peak in {} slabs of {} B",
+            self.name,
+            self.object_size,
+            self.in_use,
+            self.free,
+            self.high_water_mark,
+            self.slabs,
+            self.slab_size
+        )
+    }
+}
+
+impl<T> Drop for SlabCache<T> {
+    fn drop(&mut self) {
+        let mut cache = self.inner.lock();
+        assert!(
+            cache.in_use == 0,
+            "Slab cache `{}` dropped with {} objects in use.",
+            self.name,
+            cache.in_use
+        );
+        cache.shrink();
+    }
+}
+
+impl RawCache {
+    fn alloc(&mut self) -> Option<NonNull<u8>> {
+        let object = match self.slabs.alloc() {
+            Some(object) => object,
+            None => {
+                let slab = NonNull::new(unsafe { alloc(self.slabs.slab_layout()) })?;
+                unsafe { self.slabs.add_slab(slab) };
+                self.slabs.alloc()?
+            }
+        };
+
+        self.in_use += 1;
+        self.high_water_mark = self.high_water_mark.max(self.in_use);
+        self.allocations += 1;
+        Some(object)
+    }
+
+    ///
+    /// Returns `object` to its slab. <br>
+    /// This function is unsafe as it is up to the caller to ensure that `object` was allocated from this cache.
+    ///
+    unsafe fn free(&mut self, object: NonNull<u8>) {
+        self.in_use -= 1;
+        self.frees += 1;
+
+        if let Some(slab) = self.slabs.free(object) {
+            dealloc(slab.as_ptr(), self.slabs.slab_layout());
+        }
+    }
+
+    fn shrink(&mut self) -> usize {
+        let layout = self.slabs.slab_layout();
+        self.slabs
+            .shrink(|slab| unsafe { dealloc(slab.as_ptr(), layout) })
+    }
+}
+
+impl SlabList {
+    ///
+    /// Creates an empty list for objects of `object_size` bytes aligned to `align`. <br>
+    /// Its slabs are the smallest power of two of at least a page that fits the header and
+    /// `min_objects` objects.
+    ///
+    pub(super) const fn new(object_size: usize, align: usize, min_objects: usize) -> Self {
+        let align = max(align, mem::align_of::<FreeObject>());
+        let object_size = align_up(max(object_size, mem::size_of::<FreeObject>()), align);
+        let first_object = align_up(mem::size_of::<Slab>(), align);
+        let slab_size =
+            max(first_object + object_size * min_objects, PAGE_SIZE).next_power_of_two();
+
+        SlabList {
+            object_size,
+            first_object,
+            slab_size,
+            partial_slabs: None,
+            slabs: 0,
+            empty_slabs: 0,
+            free: 0,
+        }
+    }
+
+    /// Returns the number of slabs in the list.
+    pub(super) fn slabs(&self) -> usize {
+        self.slabs
+    }
+
+    /// Returns the number of free objects in the list's slabs.
+    pub(super) fn free_objects(&self) -> usize {
+        self.free
+    }
+
+    /// Returns the layout the memory of each slab must have.
+    pub(super) fn slab_layout(&self) -> Layout {
+        Layout::from_size_align(self.slab_size, self.slab_size).unwrap()
+    }
+
+    /// Takes an object from a partially used slab, or returns `None` if all slabs are full.
+    pub(super) fn alloc(&mut self) -> Option<NonNull<u8>> {
+        let mut slab_ptr = self.partial_slabs?;
+
+        let slab = unsafe { slab_ptr.as_mut() };
+        if slab.free_count == self.capacity() {
+            self.empty_slabs -= 1;
+        }
+        let object = slab.free_list.unwrap();
+        slab.free_list = unsafe { object.as_ref() }.next;
+        slab.free_count -= 1;
+
+        if slab.free_count == 0 {
+            self.unlink(slab_ptr);
+        }
+        self.free -= 1;
+        Some(object.cast())
+    }
+
+    ///
+    /// Puts `object` back into its slab, returning the slab if it became empty and is no longer needed. <br>
+    /// The list keeps one empty slab to avoid thrashing, the owner releases any other.
+    /// This function is unsafe as it is up to the caller to ensure that `object` was allocated from this list.
+    ///
+    #[must_use]
+    pub(super) unsafe fn free(&mut self, object: NonNull<u8>) -> Option<NonNull<u8>> {
+        let slab_start = object.as_ptr() as usize & !(self.slab_size - 1);
+        let mut slab_ptr = NonNull::new_unchecked(slab_start as *mut Slab);
+        let slab = slab_ptr.as_mut();
+
+        let mut object = object.cast::<FreeObject>();
+        object.as_mut().next = slab.free_list;
+        slab.free_list = Some(object);
+        slab.free_count += 1;
+        self.free += 1;
+
+        let free_count = slab.free_count;
+        if free_count == 1 {
+            self.link(slab_ptr); // Was full
+        }
+        if free_count == self.capacity() {
+            if self.empty_slabs > 0 {
+                self.remove(slab_ptr);
+                return Some(slab_ptr.cast());
+            }
+            self.empty_slabs += 1;
+        }
+        None
+    }
+
+    ///
+    /// Splits the memory at `start` into free objects and adds it as a new slab. <br>
+    /// This function is unsafe as it is up to the caller to ensure that `start` is unused memory of `slab_layout`.
+    ///
+    pub(super) unsafe fn add_slab(&mut self, start: NonNull<u8>) {
+        let start = start.as_ptr() as usize;
+        let capacity = self.capacity();
+        let mut free_list = None;
+        for index in (0..capacity).rev() {
+            let object = (start + self.first_object + index * self.object_size) as *mut FreeObject;
+            object.write(FreeObject { next: free_list });
+            free_list = NonNull::new(object);
+        }
+
+        let slab = NonNull::new_unchecked(start as *mut Slab);
+        slab.as_ptr().write(Slab {
+            free_list,
+            free_count: capacity,
+            previous: None,
+            next: None,
+        });
+
+        self.slabs += 1;
+        self.empty_slabs += 1;
+        self.free += capacity;
+        self.link(slab);
+    }
+
+    /// Removes all empty slabs, passing each to `release`, and returns the number of bytes released.
+    pub(super) fn shrink(&mut self, mut release: impl FnMut(NonNull<u8>)) -> usize {
+        let mut released = 0;
+        let mut current = self.partial_slabs;
+
+        while let Some(slab) = current {
+            let (next, free_count) = {
+                let slab = unsafe { slab.as_ref() };
+                (slab.next, slab.free_count)
+            };
+            if free_count == self.capacity() {
+                self.remove(slab);
+                release(slab.cast());
+                released += self.slab_size;
+            }
+            current = next;
+        }
+
+        self.empty_slabs = 0;
+        released
+    }
+
+    /// Takes the empty slab `slab` out of the list.
+    fn remove(&mut self, slab: NonNull<Slab>) {
+        self.unlink(slab);
+        self.slabs -= 1;
+        self.free -= self.capacity();
+    }
+
+    fn link(&mut self, mut slab: NonNull<Slab>) {
+        let next = self.partial_slabs;
+        if let Some(mut next) = next {
+            unsafe { next.as_mut() }.previous = Some(slab);
+        }
+
+        let slab_ref = unsafe { slab.as_mut() };
+        slab_ref.previous = None;
+        slab_ref.next = next;
+        self.partial_slabs = Some(slab);
+    }
+
+    fn unlink(&mut self, slab: NonNull<Slab>) {
+        let (previous, next) = {
+            let slab = unsafe { slab.as_ref() };
+            (slab.previous, slab.next)
+        };
+
+        match previous {
+            Some(mut previous) => unsafe { previous.as_mut() }.next = next,
+            None => self.partial_slabs = next,
+        }
+        if let Some(mut next) = next {
+            unsafe { next.as_mut() }.previous = previous;
+        }
+    }
+
+    fn capacity(&self) -> usize {
+        (self.slab_size - self.first_object) / self.object_size
+    }
+}
+
+const fn max(a: usize, b: usize) -> usize {
+    if a > b {
+        a
+    } else {
+        b
+    }
+}
+
+pub(super) const fn align_up(addr: usize, align: usize) -> usize {
+    (addr + align - 1) & !(align - 1)
+}
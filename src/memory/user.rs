@@ -0,0 +1,132 @@
+use super::paging;
+use core::arch::{asm, global_asm};
+use x86_64::{
+    registers::control::{Cr4, Cr4Flags},
+    structures::paging::PageTableFlags as PTFlags,
+    VirtAddr,
+};
+
+/// End of the lower half of the address space, the only part user memory may lie in.
+pub const USER_END: u64 = 0x0000_8000_0000_0000;
+const PAGE_SIZE: u64 = 4096;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UserCopyError {
+    BadAddress, // Part of the range is not user memory
+    Fault,      // A page of the range could not be accessed, e.g. as it is not mapped
+}
+
+// Copies `rdx` bytes from `rsi` to `rdi`, returning the number of bytes not copied in `rax`.
+// A page fault on the copying instruction resumes at `__user_copy_done`, where `rcx` still
+// holds the bytes left.
+global_asm!(
+    ".global __user_copy",
+    ".global __user_copy_access",
+    ".global __user_copy_done",
+    "__user_copy:",
+    "    mov rcx, rdx",
+    "__user_copy_access:",
+    "    rep movsb",
+    "__user_copy_done:",
+    "    mov rax, rcx",
+    "    ret",
+);
+
+extern "C" {
+    fn __user_copy(destination: *mut u8, source: *const u8, len: usize) -> usize;
+    static __user_copy_access: u8;
+    static __user_copy_done: u8;
+}
+
+///
+/// Copies `destination.len()` bytes from user memory at `source` into `destination`. <br>
+/// Fails without copying if the range is not user memory, and part-way if a page faults.
+///
+pub fn copy_from_user(destination: &mut [u8], source: VirtAddr) -> Result<(), UserCopyError> {
+    check_user_range(source, destination.len(), false)?;
+    copy(destination.as_mut_ptr(), source.as_ptr(), destination.len())
+}
+
+///
+/// Copies `source` into user memory at `destination`. <br>
+/// Fails without copying if the range is not writable user memory, and part-way if a page faults.
+///
+pub fn copy_to_user(destination: VirtAddr, source: &[u8]) -> Result<(), UserCopyError> {
+    check_user_range(destination, source.len(), true)?;
+    copy(destination.as_mut_ptr(), source.as_ptr(), source.len())
+}
+
+///
+/// Returns where to resume after a page fault at `instruction_pointer`, if it is a user
+/// memory access of the copy helpers. <br>
+/// Used by the page fault handler.
+///
+pub fn fault_fixup(instruction_pointer: VirtAddr) -> Option<VirtAddr> {
+    let (access, done) = unsafe {
+        (
+            VirtAddr::from_ptr(&__user_copy_access),
+            VirtAddr::from_ptr(&__user_copy_done),
+        )
+    };
+    if instruction_pointer == access {
+        Some(done)
+    } else {
+        None
+    }
+}
+
+fn copy(destination: *mut u8, source: *const u8, len: usize) -> Result<(), UserCopyError> {
+    // SMAP faults on kernel accesses to user pages unless `RFLAGS.AC` is set around them.
+    let smap = Cr4::read().contains(Cr4Flags::SUPERVISOR_MODE_ACCESS_PREVENTION);
+
+    let remaining = unsafe {
+        if smap {
+            asm!("stac", options(nostack));
+        }
+        let remaining = __user_copy(destination, source, len);
+        if smap {
+            asm!("clac", options(nostack));
+        }
+        remaining
+    };
+
+    match remaining {
+        0 => Ok(()),
+        _ => Err(UserCopyError::Fault),
+    }
+}
+
+///
+/// Checks that `len` bytes from `start` lie in the lower half and that their mapped pages are
+/// user accessible, and writable if `write`. <br>
+/// Pages not mapped yet are left to fault, as they may be mapped on demand.
+///
+fn check_user_range(start: VirtAddr, len: usize, write: bool) -> Result<(), UserCopyError> {
+    let end = start
+        .as_u64()
+        .checked_add(len as u64)
+        .ok_or(UserCopyError::BadAddress)?;
+    if end > USER_END {
+        return Err(UserCopyError::BadAddress);
+    }
+
+    let mut required = PTFlags::USER_ACCESSIBLE;
+    required.set(PTFlags::WRITABLE, write);
+
+    let mut page = start.align_down(PAGE_SIZE).as_u64();
+    while page < end {
+        let path = paging::translation_path(VirtAddr::new(page));
+        if let Some(path) = path.filter(|path| path.physical.is_some()) {
+            let allowed = path
+                .steps
+                .iter()
+                .flatten()
+                .all(|step| step.flags.contains(required));
+            if !allowed {
+                return Err(UserCopyError::BadAddress);
+            }
+        }
+        page += PAGE_SIZE;
+    }
+    Ok(())
+}
@@ -0,0 +1,239 @@
+use super::{
+    address_space::{self, MapError, Permissions},
+    lazy::{self, LazyError},
+};
+use crate::sync::IrqSafeMutex;
+use x86_64::{PhysAddr, VirtAddr};
+
+/// Start of the kernel virtual address range managed here, level 4 entry 320.
+pub const VMALLOC_START: u64 = 0xffff_a000_0000_0000;
+pub const VMALLOC_SIZE: u64 = 512 * 1024 * 1024 * 1024; // 512GiB, one level 4 entry
+const PAGE_SIZE: u64 = 4096;
+const GUARD_PAGES: u64 = 1; // Unmapped pages kept before and after each area
+const MAX_AREAS: usize = 256;
+
+/// Areas sorted by start, in a fixed table so that reserving never allocates, e.g. for the heap.
+static AREAS: IrqSafeMutex<AreaTable> = IrqSafeMutex::new(AreaTable {
+    areas: [Area::EMPTY; MAX_AREAS],
+    len: 0,
+});
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VmallocError {
+    OutOfAddressSpace,
+    OutOfMemory,
+    AlreadyMapped,
+    TooManyAreas,
+    TooManyLazyAreas,
+}
+
+/// What an area's pages are mapped to, deciding what happens to them when it is freed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Backing {
+    None,     // Only reserved, mapped by the owner if at all
+    Frames,   // Frames allocated for the area, returned to the frame allocator
+    Lazy,     // Frames allocated as pages are touched, returned like `Frames`
+    Physical, // Existing memory such as MMIO, left alone
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Area {
+    start: u64,
+    pages: u64,
+    backing: Backing,
+}
+
+struct AreaTable {
+    areas: [Area; MAX_AREAS],
+    len: usize,
+}
+
+impl Area {
+    const EMPTY: Area = Area {
+        start: 0,
+        pages: 0,
+        backing: Backing::None,
+    };
+
+    fn end(&self) -> u64 {
+        self.start + self.pages * PAGE_SIZE
+    }
+}
+
+impl AreaTable {
+    fn iter(&self) -> impl Iterator<Item = &Area> {
+        self.areas[..self.len].iter()
+    }
+
+    fn insert(&mut self, index: usize, area: Area) -> Result<(), VmallocError> {
+        if self.len == MAX_AREAS {
+            return Err(VmallocError::TooManyAreas);
+        }
+
+        self.areas.copy_within(index..self.len, index + 1);
+        self.areas[index] = area;
+        self.len += 1;
+        Ok(())
+    }
+
+    fn remove(&mut self, index: usize) -> Area {
+        let area = self.areas[index];
+        self.areas.copy_within(index + 1..self.len, index);
+        self.len -= 1;
+        area
+    }
+}
+
+impl From<MapError> for VmallocError {
+    fn from(error: MapError) -> Self {
+        match error {
+            MapError::OutOfMemory => VmallocError::OutOfMemory,
+            MapError::AlreadyMapped | MapError::NotMapped => VmallocError::AlreadyMapped,
+        }
+    }
+}
+
+impl From<LazyError> for VmallocError {
+    fn from(error: LazyError) -> Self {
+        match error {
+            LazyError::TooManyRegions => VmallocError::TooManyLazyAreas,
+            LazyError::Overlapping => VmallocError::AlreadyMapped,
+        }
+    }
+}
+
+///
+/// Reserves `size` bytes of kernel address space without mapping them, e.g. for stacks
+/// mapped by their owner. <br>
+/// Like all areas, it is surrounded by unmapped guard pages. Released with `vfree`.
+///
+pub fn reserve(size: usize) -> Result<VirtAddr, VmallocError> {
+    allocate_area(pages_for(size), Backing::None)
+}
+
+///
+/// Allocates `size` bytes of virtually contiguous, writable kernel memory, backed by frames
+/// that need not be physically contiguous. <br>
+/// Freed with `vfree`, which also returns the frames.
+///
+pub fn vmalloc(size: usize) -> Result<VirtAddr, VmallocError> {
+    let pages = pages_for(size);
+    let start = allocate_area(pages, Backing::Frames)?;
+
+    address_space::with(|space| {
+        space.map_range(start, (pages * PAGE_SIZE) as usize, Permissions::READ_WRITE)
+    })
+    .map(|_| start)
+    .map_err(|error| {
+        release_area(start);
+        error.into()
+    })
+}
+
+///
+/// Reserves `size` bytes of writable kernel memory backed on demand: each page gets a zeroed
+/// frame when first touched, so large areas cost nothing until used. <br>
+/// Freed with `vfree`, which also returns the frames of touched pages.
+///
+pub fn vmalloc_lazy(size: usize) -> Result<VirtAddr, VmallocError> {
+    let pages = pages_for(size);
+    let start = allocate_area(pages, Backing::Lazy)?;
+
+    lazy::register(start, (pages * PAGE_SIZE) as usize, Permissions::READ_WRITE)
+        .map(|_| start)
+        .map_err(|error| {
+            release_area(start);
+            error.into()
+        })
+}
+
+///
+/// Maps `size` bytes of physical memory starting at `start`, e.g. device registers, into
+/// kernel address space with caching disabled. <br>
+/// Unmapped with `vfree`, which leaves the memory itself alone.
+///
+pub fn map_mmio(start: PhysAddr, size: usize) -> Result<VirtAddr, VmallocError> {
+    let offset = start.as_u64() % PAGE_SIZE;
+    let pages = pages_for(offset as usize + size);
+    let area_start = allocate_area(pages, Backing::Physical)?;
+    let virtual_start = area_start + offset;
+
+    let permissions = Permissions::READ_WRITE.uncached();
+    address_space::with(|space| space.map_physical_range(virtual_start, start, size, permissions))
+        .map(|_| virtual_start)
+        .map_err(|error| {
+            release_area(area_start);
+            error.into()
+        })
+}
+
+/// Unmaps and releases the area containing `address`, returned by `reserve`, `vmalloc`,
+/// `vmalloc_lazy` or `map_mmio`.
+///
+/// This function is unsafe as it is up to the caller to ensure that the area is no longer
+/// in use. Pages mapped into reserved areas are unmapped, but their frames are not returned.
+pub unsafe fn vfree(address: VirtAddr) {
+    let area = release_area(address).expect("vfree of an address outside any area.");
+    let start = VirtAddr::new(area.start);
+    let size = (area.pages * PAGE_SIZE) as usize;
+
+    if area.backing == Backing::Lazy {
+        lazy::unregister(start);
+    }
+
+    address_space::with(|space| match area.backing {
+        Backing::Frames | Backing::Lazy => space.unmap_range(start, size),
+        Backing::None | Backing::Physical => space.unmap_physical_range(start, size),
+    })
+    .expect("Failed to unmap a vmalloc area.");
+}
+
+/// Returns the number of bytes of address space handed out, excluding guard pages.
+pub fn used() -> u64 {
+    AREAS.lock().iter().map(|area| area.pages * PAGE_SIZE).sum()
+}
+
+/// Finds the first gap fitting `pages` plus guard pages and records an area there.
+fn allocate_area(pages: u64, backing: Backing) -> Result<VirtAddr, VmallocError> {
+    let mut areas = AREAS.lock();
+    let needed = (pages + GUARD_PAGES) * PAGE_SIZE;
+
+    let mut start = VMALLOC_START + GUARD_PAGES * PAGE_SIZE;
+    let mut index = areas.len;
+    for (i, area) in areas.iter().enumerate() {
+        if start + needed <= area.start {
+            index = i;
+            break;
+        }
+        start = area.end() + GUARD_PAGES * PAGE_SIZE;
+    }
+
+    if start + needed > VMALLOC_START + VMALLOC_SIZE {
+        return Err(VmallocError::OutOfAddressSpace);
+    }
+
+    areas.insert(
+        index,
+        Area {
+            start,
+            pages,
+            backing,
+        },
+    )?;
+    Ok(VirtAddr::new(start))
+}
+
+/// Removes the area containing `address` from the records and returns it.
+fn release_area(address: VirtAddr) -> Option<Area> {
+    let mut areas = AREAS.lock();
+    let address = address.as_u64();
+    let index = areas
+        .iter()
+        .position(|area| (area.start..area.end()).contains(&address))?;
+
+    Some(areas.remove(index))
+}
+
+fn pages_for(size: usize) -> u64 {
+    ((size as u64 + PAGE_SIZE - 1) / PAGE_SIZE).max(1)
+}
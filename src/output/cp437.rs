@@ -0,0 +1,86 @@
+/// Glyph used for characters that have no code page 437 equivalent ("■").
+pub const REPLACEMENT: u8 = 0xfe;
+
+/// Glyphs for code points 0x00-0x1f, which VGA draws as symbols rather than control characters.
+const LOW_GLYPHS: [char; 32] = [
+    '\0', '☺', '☻', '♥', '♦', '♣', '♠', '•', '◘', '○', '◙', '♂', '♀', '♪', '♫', '☼', //
+    '►', '◄', '↕', '‼', '¶', '§', '▬', '↨', '↑', '↓', '→', '←', '∟', '↔', '▲', '▼',
+];
+
+/// Glyph for code point 0x7f.
+const HOUSE: char = '⌂';
+
+/// Glyphs for code points 0x80-0xff.
+const HIGH_GLYPHS: [char; 128] = [
+    'Ç', 'ü', 'é', 'â', 'ä', 'à', 'å', 'ç', 'ê', 'ë', 'è', 'ï', 'î', 'ì', 'Ä', 'Å', //
+    'É', 'æ', 'Æ', 'ô', 'ö', 'ò', 'û', 'ù', 'ÿ', 'Ö', 'Ü', '¢', '£', '¥', '₧', 'ƒ', //
+    'á', 'í', 'ó', 'ú', 'ñ', 'Ñ', 'ª', 'º', '¿', '⌐', '¬', '½', '¼', '¡', '«', '»', //
+    '░', '▒', '▓', '│', '┤', '╡', '╢', '╖', '╕', '╣', '║', '╗', '╝', '╜', '╛', '┐', //
+    '└', '┴', '┬', '├', '─', '┼', '╞', '╟', '╚', '╔', '╩', '╦', '╠', '═', '╬', '╧', //
+    '╨', '╤', '╥', '╙', '╘', '╒', '╓', '╫', '╪', '┘', '┌', '█', '▄', '▌', '▐', '▀', //
+    'α', 'ß', 'Γ', 'π', 'Σ', 'σ', 'µ', 'τ', 'Φ', 'Θ', 'Ω', 'δ', '∞', 'φ', 'ε', '∩', //
+    '≡', '±', '≥', '≤', '⌠', '⌡', '÷', '≈', '°', '∙', '·', '√', 'ⁿ', '²', '■', '\u{a0}',
+];
+
+/// Characters that look the same as an existing glyph but have a different code point.
+const ALIASES: [(char, u8); 7] = [
+    ('β', 0xe1),        // Greek beta drawn as sharp s
+    ('μ', 0xe6),        // Greek mu vs. micro sign
+    ('∑', 0xe4),        // N-ary summation vs. Greek capital sigma
+    ('\u{2126}', 0xea), // Ohm sign vs. Greek capital omega
+    ('∈', 0xee),        // Element of vs. Greek epsilon
+    ('ϕ', 0xed),        // Greek phi symbol vs. Greek phi
+    ('∅', 0xed),        // Empty set vs. Greek phi
+];
+
+///
+/// Returns the code page 437 glyph for `character`, if there is one. <br>
+/// Control characters are not mapped; callers are expected to interpret them first.
+///
+pub fn from_char(character: char) -> Option<u8> {
+    match character {
+        ' '..='~' => Some(character as u8),
+        HOUSE => Some(0x7f),
+        '\0'..='\u{7f}' => None,
+        _ => LOW_GLYPHS
+            .iter()
+            .position(|&glyph| glyph == character)
+            .map(|index| index as u8)
+            .or_else(|| {
+                HIGH_GLYPHS
+                    .iter()
+                    .position(|&glyph| glyph == character)
+                    .map(|index| 0x80 + index as u8)
+            })
+            .or_else(|| {
+                ALIASES
+                    .iter()
+                    .find(|&&(alias, _)| alias == character)
+                    .map(|&(_, glyph)| glyph)
+            }),
+    }
+}
+
+#[test_case]
+fn test_cp437_ascii() {
+    assert_eq!(from_char('A'), Some(b'A'));
+    assert_eq!(from_char('~'), Some(b'~'));
+    assert_eq!(from_char('\x07'), None);
+}
+
+#[test_case]
+fn test_cp437_glyphs() {
+    assert_eq!(from_char('é'), Some(0x82));
+    assert_eq!(from_char('═'), Some(0xcd));
+    assert_eq!(from_char('π'), Some(0xe3));
+    assert_eq!(from_char('→'), Some(0x1a));
+    assert_eq!(from_char('▒'), Some(0xb1));
+    assert_eq!(from_char('⌂'), Some(0x7f));
+    assert_eq!(from_char('β'), Some(0xe1));
+}
+
+#[test_case]
+fn test_cp437_unmappable() {
+    assert_eq!(from_char('€'), None);
+    assert_eq!(from_char('日'), None);
+}
@@ -0,0 +1,345 @@
+use super::{
+    psf::{Font, FontError},
+    vga::ColorCode,
+};
+use crate::{
+    memory::vmalloc::{self, VmallocError},
+    sync::IrqSafeMutex,
+};
+use core::{
+    fmt,
+    sync::atomic::{AtomicBool, Ordering},
+};
+use x86_64::{PhysAddr, VirtAddr};
+
+const TAB_WIDTH: usize = 4;
+
+/// X11 misc-fixed 8x13 (public domain), padded to 8x16 cells with a PSF2 unicode table.
+static DEFAULT_FONT: &[u8] = include_bytes!("fonts/fixed-8x16.psf");
+
+static FRAMEBUFFER: IrqSafeMutex<Option<FrameBufferWriter>> = IrqSafeMutex::new(None);
+static INITIALIZED: AtomicBool = AtomicBool::new(false);
+
+/// Returns `true` once `init` has set up the framebuffer console.
+pub fn is_initialized() -> bool {
+    INITIALIZED.load(Ordering::SeqCst)
+}
+
+///
+/// Runs `f` with the framebuffer console, if it has been initialized. <br>
+/// Interrupts are disabled for the duration to prevent deadlocks.
+///
+pub fn with_writer<R>(f: impl FnOnce(&mut FrameBufferWriter) -> R) -> Option<R> {
+    FRAMEBUFFER.lock().as_mut().map(f)
+}
+
+///
+/// Releases the framebuffer console lock regardless of its holder. <br>
+/// Only for the panic handler, see `panicking::prepare`.
+///
+pub(crate) unsafe fn force_unlock() {
+    FRAMEBUFFER.force_unlock();
+}
+
+#[derive(Debug)]
+pub enum FrameBufferError {
+    DeviceNotFound,
+    UnsupportedMode,
+    Font(FontError),
+    Map(VmallocError),
+}
+
+///
+/// Describes a linear framebuffer in physical memory.
+///
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FrameBufferInfo {
+    pub phys_addr: PhysAddr,
+    pub width: usize,
+    pub height: usize,
+    pub pitch: usize, // Bytes per scanline
+    pub bytes_per_pixel: usize,
+}
+
+impl FrameBufferInfo {
+    pub fn size(&self) -> usize {
+        self.pitch * self.height
+    }
+}
+
+///
+/// Switches the Bochs/QEMU VBE display adapter to a `width`x`height` 32-bit mode,
+/// maps its linear framebuffer and routes `print!` to it.
+///
+pub fn init(width: usize, height: usize) -> Result<(), FrameBufferError> {
+    let info = bochs::set_mode(width, height)?;
+    init_with(info)
+}
+
+///
+/// Maps an already configured linear framebuffer described by `info` into a `vmalloc` area,
+/// e.g. one provided by the bootloader, and routes `print!` to it.
+///
+pub fn init_with(info: FrameBufferInfo) -> Result<(), FrameBufferError> {
+    if info.bytes_per_pixel != 3 && info.bytes_per_pixel != 4 {
+        return Err(FrameBufferError::UnsupportedMode);
+    }
+
+    let font = Font::parse(DEFAULT_FONT).map_err(FrameBufferError::Font)?;
+    let start = vmalloc::map_mmio(info.phys_addr, info.size()).map_err(FrameBufferError::Map)?;
+
+    let mut writer = FrameBufferWriter::new(info, start, font);
+    writer.clear();
+
+    *FRAMEBUFFER.lock() = Some(writer);
+    INITIALIZED.store(true, Ordering::SeqCst);
+
+    Ok(())
+}
+
+///
+/// Writer struct rendering text to a linear framebuffer using a PSF font.
+///
+pub struct FrameBufferWriter {
+    info: FrameBufferInfo,
+    start: VirtAddr,
+    font: Font,
+    columns: usize,
+    rows: usize,
+    pub color_code: ColorCode,
+    pub cursor_position: (usize, usize),
+}
+
+impl FrameBufferWriter {
+    fn new(info: FrameBufferInfo, start: VirtAddr, font: Font) -> Self {
+        FrameBufferWriter {
+            info,
+            start,
+            columns: info.width / font.width,
+            rows: info.height / font.height,
+            font,
+            color_code: ColorCode::default(),
+            cursor_position: (0, 0), // x, y | col, row
+        }
+    }
+
+    pub fn info(&self) -> FrameBufferInfo {
+        self.info
+    }
+
+    pub fn font(&self) -> &Font {
+        &self.font
+    }
+
+    /// Reads back the pixel at (`x`, `y`) as `0xRRGGBB`.
+    pub fn pixel(&self, x: usize, y: usize) -> u32 {
+        let ptr = self.pixel_ptr(x, y);
+
+        unsafe {
+            match self.info.bytes_per_pixel {
+                4 => (ptr as *const u32).read_volatile() & 0xff_ffff,
+                _ => {
+                    u32::from(ptr.read_volatile())
+                        | u32::from(ptr.add(1).read_volatile()) << 8
+                        | u32::from(ptr.add(2).read_volatile()) << 16
+                }
+            }
+        }
+    }
+
+    /// Sets the pixel at (`x`, `y`) to `rgb`, given as `0xRRGGBB`.
+    pub fn set_pixel(&mut self, x: usize, y: usize, rgb: u32) {
+        let ptr = self.pixel_ptr(x, y);
+
+        unsafe {
+            match self.info.bytes_per_pixel {
+                4 => (ptr as *mut u32).write_volatile(rgb),
+                _ => {
+                    ptr.write_volatile(rgb as u8);
+                    ptr.add(1).write_volatile((rgb >> 8) as u8);
+                    ptr.add(2).write_volatile((rgb >> 16) as u8);
+                }
+            }
+        }
+    }
+
+    fn pixel_ptr(&self, x: usize, y: usize) -> *mut u8 {
+        assert!(x < self.info.width && y < self.info.height);
+
+        let offset = y * self.info.pitch + x * self.info.bytes_per_pixel;
+        (self.start + offset).as_mut_ptr()
+    }
+
+    /// Fills the whole framebuffer with the background colour.
+    pub fn clear(&mut self) {
+        let background = self.color_code.background_color.rgb();
+
+        for y in 0..self.info.height {
+            for x in 0..self.info.width {
+                self.set_pixel(x, y, background);
+            }
+        }
+        self.cursor_position = (0, 0);
+    }
+
+    fn draw_char(&mut self, character: char, col: usize, row: usize) {
+        let glyph = self.font.glyph(character);
+        let bytes_per_row = self.font.bytes_per_row();
+        let foreground = self.color_code.foreground_color.rgb();
+        let background = self.color_code.background_color.rgb();
+
+        for y in 0..self.font.height {
+            let line = &glyph[y * bytes_per_row..(y + 1) * bytes_per_row];
+
+            for x in 0..self.font.width {
+                let set = line[x / 8] & (0x80 >> (x % 8)) != 0;
+                let color = if set { foreground } else { background };
+
+                self.set_pixel(col * self.font.width + x, row * self.font.height + y, color);
+            }
+        }
+    }
+
+    fn clear_row(&mut self, row: usize) {
+        let background = self.color_code.background_color.rgb();
+
+        for y in row * self.font.height..(row + 1) * self.font.height {
+            for x in 0..self.info.width {
+                self.set_pixel(x, y, background);
+            }
+        }
+    }
+
+    fn new_line(&mut self) {
+        self.cursor_position.0 = 0;
+
+        if (self.cursor_position.1 + 1) < self.rows {
+            self.cursor_position.1 += 1;
+        } else {
+            // Move every text row but the first up by one glyph height.
+            let row_bytes = self.info.pitch * self.font.height;
+            let src: *const u8 = (self.start + row_bytes).as_ptr();
+            let dst: *mut u8 = self.start.as_mut_ptr();
+            unsafe {
+                core::ptr::copy(src, dst, row_bytes * (self.rows - 1));
+            }
+
+            self.clear_row(self.rows - 1);
+            self.cursor_position.1 = self.rows - 1;
+        }
+    }
+
+    pub fn write_char(&mut self, character: char) {
+        match character {
+            '\n' => self.new_line(),
+            '\t' => {
+                for _ in 0..TAB_WIDTH {
+                    self.write_char(' ');
+                }
+            }
+            _ => {
+                if self.cursor_position.0 >= self.columns {
+                    self.new_line();
+                }
+
+                let (col, row) = self.cursor_position;
+                self.draw_char(character, col, row);
+                self.cursor_position.0 += 1;
+            }
+        }
+    }
+
+    pub fn write_string(&mut self, s: &str) {
+        for character in s.chars() {
+            self.write_char(character);
+        }
+    }
+}
+
+impl fmt::Write for FrameBufferWriter {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.write_string(s);
+        Ok(())
+    }
+}
+
+///
+/// The Bochs/QEMU "dispi" VBE extensions, programmed through two I/O ports.
+///
+mod bochs {
+    use super::{FrameBufferError, FrameBufferInfo};
+    use crate::pci;
+    use x86_64::{instructions::port::Port, PhysAddr};
+
+    const INDEX_PORT: u16 = 0x1ce;
+    const DATA_PORT: u16 = 0x1cf;
+
+    const INDEX_ID: u16 = 0;
+    const INDEX_XRES: u16 = 1;
+    const INDEX_YRES: u16 = 2;
+    const INDEX_BPP: u16 = 3;
+    const INDEX_ENABLE: u16 = 4;
+    const INDEX_VIRT_WIDTH: u16 = 6;
+
+    const ID_MIN: u16 = 0xb0c0;
+    const ID_MAX: u16 = 0xb0c5;
+
+    const ENABLED: u16 = 0x01;
+    const LFB_ENABLED: u16 = 0x40;
+
+    const PCI_VENDOR: u16 = 0x1234;
+    const PCI_DEVICE: u16 = 0x1111;
+
+    const BITS_PER_PIXEL: u16 = 32;
+
+    fn read(index: u16) -> u16 {
+        unsafe {
+            Port::new(INDEX_PORT).write(index);
+            Port::new(DATA_PORT).read()
+        }
+    }
+
+    fn write(index: u16, value: u16) {
+        unsafe {
+            Port::new(INDEX_PORT).write(index);
+            Port::new(DATA_PORT).write(value);
+        }
+    }
+
+    pub(super) fn set_mode(
+        width: usize,
+        height: usize,
+    ) -> Result<FrameBufferInfo, FrameBufferError> {
+        if !(ID_MIN..=ID_MAX).contains(&read(INDEX_ID)) {
+            return Err(FrameBufferError::DeviceNotFound);
+        }
+
+        let device =
+            pci::find_device(PCI_VENDOR, PCI_DEVICE).ok_or(FrameBufferError::DeviceNotFound)?;
+        let phys_addr = PhysAddr::new(u64::from(device.memory_bar(0)));
+
+        if width > usize::from(u16::MAX) || height > usize::from(u16::MAX) {
+            return Err(FrameBufferError::UnsupportedMode);
+        }
+
+        write(INDEX_ENABLE, 0);
+        write(INDEX_XRES, width as u16);
+        write(INDEX_YRES, height as u16);
+        write(INDEX_BPP, BITS_PER_PIXEL);
+        write(INDEX_ENABLE, ENABLED | LFB_ENABLED);
+
+        // The adapter silently clamps modes it cannot display.
+        if usize::from(read(INDEX_XRES)) != width || usize::from(read(INDEX_YRES)) != height {
+            return Err(FrameBufferError::UnsupportedMode);
+        }
+
+        let bytes_per_pixel = usize::from(BITS_PER_PIXEL / 8);
+        Ok(FrameBufferInfo {
+            phys_addr,
+            width,
+            height,
+            pitch: usize::from(read(INDEX_VIRT_WIDTH)) * bytes_per_pixel,
+            bytes_per_pixel,
+        })
+    }
+}
@@ -0,0 +1,626 @@
+use super::{
+    cp437, framebuffer,
+    vga::{self, Color},
+};
+use crate::sync::IrqSafeMutex;
+use x86_64::{instructions::port::Port, VirtAddr};
+
+const VGA_WINDOW: u64 = 0xa0000; // Physical address of the graphics memory window
+
+const MISC_WRITE: u16 = 0x3c2;
+const SEQUENCER: u16 = 0x3c4; // Index port, data port is +1
+const GRAPHICS_CONTROLLER: u16 = 0x3ce;
+const CRT_CONTROLLER: u16 = 0x3d4;
+const ATTRIBUTE_CONTROLLER: u16 = 0x3c0; // Index and data share the write port
+const INPUT_STATUS: u16 = 0x3da; // Reading resets the attribute controller to expect an index
+const DAC_READ_INDEX: u16 = 0x3c7;
+const DAC_WRITE_INDEX: u16 = 0x3c8;
+const DAC_DATA: u16 = 0x3c9;
+
+const GLYPH_COUNT: usize = 256;
+const GLYPH_HEIGHT: usize = 16; // Scanlines per character of the 80x25 text mode font
+const GLYPH_WIDTH: usize = 8;
+const GLYPH_STRIDE: usize = 32; // Bytes reserved per glyph in plane 2
+
+/// The 16 text mode colours, in palette order.
+const COLORS: [Color; 16] = [
+    Color::Black,
+    Color::Blue,
+    Color::Green,
+    Color::Cyan,
+    Color::Red,
+    Color::Magenta,
+    Color::Brown,
+    Color::LightGray,
+    Color::DarkGray,
+    Color::LightBlue,
+    Color::LightGreen,
+    Color::LightCyan,
+    Color::LightRed,
+    Color::Pink,
+    Color::Yellow,
+    Color::White,
+];
+
+static SCREEN: IrqSafeMutex<Screen> = IrqSafeMutex::new(Screen::new());
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GraphicsError {
+    NotInitialized,
+    FrameBufferActive, // The VBE framebuffer console owns the display
+}
+
+///
+/// Display modes of a standard VGA controller.
+///
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Mode {
+    Text,    // 80x25 text, as used by `output::vga`
+    Mode13h, // 320x200, 256 colours, one byte per pixel
+    Mode12h, // 640x480, 16 colours, four bit planes
+}
+
+impl Mode {
+    /// Returns the resolution in pixels, or in characters for `Mode::Text`.
+    pub fn resolution(self) -> (usize, usize) {
+        match self {
+            Mode::Text => (80, 25),
+            Mode::Mode13h => (320, 200),
+            Mode::Mode12h => (640, 480),
+        }
+    }
+
+    fn registers(self) -> &'static Registers {
+        match self {
+            Mode::Text => &TEXT_80X25,
+            Mode::Mode13h => &MODE_13H,
+            Mode::Mode12h => &MODE_12H,
+        }
+    }
+}
+
+///
+/// Sets up access to the graphics memory window through the physical memory mapping. <br>
+/// Must be called before `set_mode`.
+///
+pub fn init(physical_memory_offset: VirtAddr) {
+    SCREEN.lock().window = Some(physical_memory_offset + VGA_WINDOW);
+}
+
+///
+/// Switches the VGA controller to `mode` by programming its registers directly. <br>
+/// The text mode font is saved when leaving text mode and restored on return,
+/// after which the active console is redrawn.
+///
+pub fn set_mode(mode: Mode) -> Result<(), GraphicsError> {
+    if framebuffer::is_initialized() {
+        return Err(GraphicsError::FrameBufferActive);
+    }
+
+    SCREEN.lock().set_mode(mode)?;
+
+    if mode == Mode::Text {
+        vga::redraw();
+    }
+    Ok(())
+}
+
+/// Returns the current display mode.
+pub fn mode() -> Mode {
+    SCREEN.lock().mode
+}
+
+///
+/// Runs `f` with the screen if a graphics mode is set. <br>
+/// Interrupts are disabled for the duration to prevent deadlocks.
+///
+pub fn with_screen<R>(f: impl FnOnce(&mut Screen) -> R) -> Option<R> {
+    let mut screen = SCREEN.lock();
+    if screen.mode == Mode::Text {
+        return None;
+    }
+    Some(f(&mut screen))
+}
+
+///
+/// Sets palette entry `index` to `rgb`, given as `0xRRGGBB`. <br>
+/// The DAC keeps 6 bits per component, so the low 2 bits of each are dropped.
+///
+pub fn set_palette(index: u8, rgb: u32) {
+    unsafe {
+        Port::new(DAC_WRITE_INDEX).write(index);
+
+        let mut data = Port::new(DAC_DATA);
+        data.write((rgb >> 18) as u8 & 0x3f);
+        data.write((rgb >> 10) as u8 & 0x3f);
+        data.write((rgb >> 2) as u8 & 0x3f);
+    }
+}
+
+///
+/// Reads back palette entry `index` as `0xRRGGBB`. <br>
+/// The low 2 bits of each component are always 0, as the DAC only keeps 6 bits.
+///
+pub fn palette(index: u8) -> u32 {
+    unsafe {
+        Port::new(DAC_READ_INDEX).write(index);
+
+        let mut data = Port::<u8>::new(DAC_DATA);
+        let red = u32::from(data.read() & 0x3f) << 2;
+        let green = u32::from(data.read() & 0x3f) << 2;
+        let blue = u32::from(data.read() & 0x3f) << 2;
+
+        red << 16 | green << 8 | blue
+    }
+}
+
+///
+/// Loads the default palette: the 16 text mode colours, a 6x6x6 colour cube
+/// and a 24 step grey ramp, as in 256 colour terminals.
+///
+pub fn load_default_palette() {
+    for index in 0..=u8::MAX {
+        set_palette(index, default_palette(index));
+    }
+}
+
+fn default_palette(index: u8) -> u32 {
+    let level = |step: u8| {
+        if step == 0 {
+            0
+        } else {
+            55 + 40 * u32::from(step)
+        }
+    };
+
+    match index {
+        0..=15 => COLORS[usize::from(index)].rgb(),
+        16..=231 => {
+            let cube = index - 16;
+            level(cube / 36) << 16 | level(cube / 6 % 6) << 8 | level(cube % 6)
+        }
+        _ => (8 + 10 * u32::from(index - 232)) * 0x01_0101,
+    }
+}
+
+///
+/// A VGA graphics mode screen with drawing primitives. <br>
+/// Colours are palette indices; `Color` converts to the matching entry of the default palette.
+/// Drawing outside the screen is clipped.
+///
+pub struct Screen {
+    mode: Mode,
+    window: Option<VirtAddr>,
+    font: [[u8; GLYPH_HEIGHT]; GLYPH_COUNT], // Text mode font, saved from plane 2
+    cursor: [u8; 4],                         // Text mode cursor shape and location registers
+}
+
+impl Screen {
+    const fn new() -> Self {
+        Screen {
+            mode: Mode::Text,
+            window: None,
+            font: [[0; GLYPH_HEIGHT]; GLYPH_COUNT],
+            cursor: [0; 4],
+        }
+    }
+
+    fn set_mode(&mut self, mode: Mode) -> Result<(), GraphicsError> {
+        let window = self.window.ok_or(GraphicsError::NotInitialized)?;
+        if mode == self.mode {
+            return Ok(());
+        }
+
+        // Graphics modes overwrite plane 2, so the font has to be kept while away from text mode.
+        if self.mode == Mode::Text {
+            self.save_text_state(window);
+        }
+
+        write_registers(mode.registers());
+        load_default_palette();
+        self.mode = mode;
+
+        if mode == Mode::Text {
+            self.restore_text_state(window);
+        } else {
+            self.clear(Color::Black);
+        }
+        Ok(())
+    }
+
+    fn save_text_state(&mut self, window: VirtAddr) {
+        for (i, register) in self.cursor.iter_mut().enumerate() {
+            *register = read_indexed(CRT_CONTROLLER, CURSOR_REGISTERS[i]);
+        }
+
+        let font = &mut self.font;
+        with_font_plane(|| {
+            let plane: *const u8 = window.as_ptr();
+            for (i, glyph) in font.iter_mut().enumerate() {
+                for (row, line) in glyph.iter_mut().enumerate() {
+                    *line = unsafe { plane.add(i * GLYPH_STRIDE + row).read_volatile() };
+                }
+            }
+        });
+    }
+
+    fn restore_text_state(&self, window: VirtAddr) {
+        with_font_plane(|| {
+            let plane: *mut u8 = window.as_mut_ptr();
+            for (i, glyph) in self.font.iter().enumerate() {
+                for (row, &line) in glyph.iter().enumerate() {
+                    unsafe { plane.add(i * GLYPH_STRIDE + row).write_volatile(line) };
+                }
+            }
+        });
+
+        for (i, &register) in self.cursor.iter().enumerate() {
+            write_indexed(CRT_CONTROLLER, CURSOR_REGISTERS[i], register);
+        }
+    }
+
+    pub fn mode(&self) -> Mode {
+        self.mode
+    }
+
+    pub fn width(&self) -> usize {
+        self.mode.resolution().0
+    }
+
+    pub fn height(&self) -> usize {
+        self.mode.resolution().1
+    }
+
+    fn window(&self) -> *mut u8 {
+        self.window.expect("Graphics not initialized.").as_mut_ptr()
+    }
+
+    /// Sets the pixel at (`x`, `y`) to palette entry `color`.
+    pub fn set_pixel(&mut self, x: usize, y: usize, color: impl Into<u8>) {
+        if x >= self.width() || y >= self.height() {
+            return;
+        }
+        let color = color.into();
+
+        match self.mode {
+            Mode::Mode13h => unsafe {
+                self.window()
+                    .add(y * self.width() + x)
+                    .write_volatile(color);
+            },
+            Mode::Mode12h => {
+                // Write mode 2 spreads the colour's bits over the planes; the bit mask selects the pixel.
+                write_indexed(GRAPHICS_CONTROLLER, 0x08, 0x80 >> (x % 8));
+                unsafe {
+                    let byte = self.window().add((y * self.width() + x) / 8);
+                    byte.read_volatile(); // Loads the latches, keeping the byte's other pixels
+                    byte.write_volatile(color);
+                }
+            }
+            Mode::Text => {}
+        }
+    }
+
+    /// Reads back the palette entry of the pixel at (`x`, `y`).
+    pub fn pixel(&self, x: usize, y: usize) -> u8 {
+        assert!(x < self.width() && y < self.height());
+
+        match self.mode {
+            Mode::Mode13h => unsafe { self.window().add(y * self.width() + x).read_volatile() },
+            Mode::Mode12h => {
+                let byte = unsafe { self.window().add((y * self.width() + x) / 8) };
+                let mut color = 0;
+
+                for plane in 0..4 {
+                    write_indexed(GRAPHICS_CONTROLLER, 0x04, plane);
+                    let bits = unsafe { byte.read_volatile() };
+                    color |= (bits >> (7 - x % 8) & 1) << plane;
+                }
+                color
+            }
+            Mode::Text => 0,
+        }
+    }
+
+    /// Fills the whole screen with `color`.
+    pub fn clear(&mut self, color: impl Into<u8>) {
+        let color = color.into();
+        let (width, height) = (self.width(), self.height());
+
+        match self.mode {
+            Mode::Mode13h => {
+                for offset in 0..width * height {
+                    unsafe { self.window().add(offset).write_volatile(color) };
+                }
+            }
+            Mode::Mode12h => {
+                write_indexed(GRAPHICS_CONTROLLER, 0x08, 0xff);
+                for offset in 0..width * height / 8 {
+                    unsafe { self.window().add(offset).write_volatile(color) };
+                }
+            }
+            Mode::Text => {}
+        }
+    }
+
+    /// Draws a line from `from` to `to` using Bresenham's algorithm.
+    pub fn draw_line(&mut self, from: (isize, isize), to: (isize, isize), color: impl Into<u8>) {
+        let color = color.into();
+        let (mut x, mut y) = from;
+        let dx = (to.0 - x).abs();
+        let dy = -(to.1 - y).abs();
+        let step_x = if x < to.0 { 1 } else { -1 };
+        let step_y = if y < to.1 { 1 } else { -1 };
+        let mut error = dx + dy;
+
+        loop {
+            if x >= 0 && y >= 0 {
+                self.set_pixel(x as usize, y as usize, color);
+            }
+            if (x, y) == to {
+                break;
+            }
+
+            let doubled = 2 * error;
+            if doubled >= dy {
+                error += dy;
+                x += step_x;
+            }
+            if doubled <= dx {
+                error += dx;
+                y += step_y;
+            }
+        }
+    }
+
+    /// Draws the outline of a `width`x`height` rectangle with its top left corner at (`x`, `y`).
+    pub fn draw_rect(
+        &mut self,
+        x: usize,
+        y: usize,
+        width: usize,
+        height: usize,
+        color: impl Into<u8>,
+    ) {
+        if width == 0 || height == 0 {
+            return;
+        }
+        let color = color.into();
+        let (right, bottom) = (x + width - 1, y + height - 1);
+
+        for column in x..=right {
+            self.set_pixel(column, y, color);
+            self.set_pixel(column, bottom, color);
+        }
+        for row in y..=bottom {
+            self.set_pixel(x, row, color);
+            self.set_pixel(right, row, color);
+        }
+    }
+
+    /// Fills a `width`x`height` rectangle with its top left corner at (`x`, `y`).
+    pub fn fill_rect(
+        &mut self,
+        x: usize,
+        y: usize,
+        width: usize,
+        height: usize,
+        color: impl Into<u8>,
+    ) {
+        let color = color.into();
+
+        for row in y..y + height {
+            for column in x..x + width {
+                self.set_pixel(column, row, color);
+            }
+        }
+    }
+
+    ///
+    /// Copies `pixels`, rows of `width` palette indices, to the screen at (`x`, `y`). <br>
+    /// A trailing partial row is drawn as far as it goes.
+    ///
+    pub fn blit(&mut self, x: usize, y: usize, width: usize, pixels: &[u8]) {
+        if width == 0 {
+            return;
+        }
+
+        for (i, &color) in pixels.iter().enumerate() {
+            self.set_pixel(x + i % width, y + i / width, color);
+        }
+    }
+
+    ///
+    /// Draws `text` with the text mode font, starting with the top left corner at (`x`, `y`). <br>
+    /// Characters are 8x16 pixels and `\n` continues on the next line at `x`.
+    ///
+    pub fn draw_text(
+        &mut self,
+        x: usize,
+        y: usize,
+        text: &str,
+        foreground: impl Into<u8>,
+        background: impl Into<u8>,
+    ) {
+        let (foreground, background) = (foreground.into(), background.into());
+        let (mut column, mut row) = (x, y);
+
+        for character in text.chars() {
+            if character == '\n' {
+                column = x;
+                row += GLYPH_HEIGHT;
+                continue;
+            }
+
+            // Unmappable characters -> prints "■"
+            let glyph = cp437::from_char(character).unwrap_or(cp437::REPLACEMENT);
+            let lines = self.font[usize::from(glyph)];
+
+            for (dy, line) in lines.iter().enumerate() {
+                for dx in 0..GLYPH_WIDTH {
+                    let set = line & (0x80 >> dx) != 0;
+                    let color = if set { foreground } else { background };
+                    self.set_pixel(column + dx, row + dy, color);
+                }
+            }
+            column += GLYPH_WIDTH;
+        }
+    }
+}
+
+fn write_indexed(port: u16, index: u8, value: u8) {
+    unsafe {
+        Port::new(port).write(index);
+        Port::new(port + 1).write(value);
+    }
+}
+
+fn read_indexed(port: u16, index: u8) -> u8 {
+    unsafe {
+        Port::new(port).write(index);
+        Port::new(port + 1).read()
+    }
+}
+
+///
+/// Runs `f` with plane 2, which holds the text mode font, mapped linearly at the graphics window. <br>
+/// The affected sequencer and graphics controller registers are restored afterwards.
+///
+fn with_font_plane(f: impl FnOnce()) {
+    let map_mask = read_indexed(SEQUENCER, 0x02);
+    let memory_mode = read_indexed(SEQUENCER, 0x04);
+    let read_map = read_indexed(GRAPHICS_CONTROLLER, 0x04);
+    let graphics_mode = read_indexed(GRAPHICS_CONTROLLER, 0x05);
+    let miscellaneous = read_indexed(GRAPHICS_CONTROLLER, 0x06);
+
+    write_indexed(SEQUENCER, 0x02, 0x04); // Write to plane 2 only
+    write_indexed(SEQUENCER, 0x04, 0x06); // Sequential addressing, no odd/even
+    write_indexed(GRAPHICS_CONTROLLER, 0x04, 0x02); // Read from plane 2
+    write_indexed(GRAPHICS_CONTROLLER, 0x05, 0x00); // Write mode 0, no odd/even
+    write_indexed(GRAPHICS_CONTROLLER, 0x06, 0x04); // Map 64 KiB at 0xa0000
+
+    f();
+
+    write_indexed(SEQUENCER, 0x02, map_mask);
+    write_indexed(SEQUENCER, 0x04, memory_mode);
+    write_indexed(GRAPHICS_CONTROLLER, 0x04, read_map);
+    write_indexed(GRAPHICS_CONTROLLER, 0x05, graphics_mode);
+    write_indexed(GRAPHICS_CONTROLLER, 0x06, miscellaneous);
+}
+
+fn write_registers(registers: &Registers) {
+    unsafe {
+        Port::new(MISC_WRITE).write(registers.misc);
+    }
+
+    for (index, &value) in registers.sequencer.iter().enumerate() {
+        write_indexed(SEQUENCER, index as u8, value);
+    }
+
+    // CRTC registers 0-7 are write protected by bit 7 of the vertical retrace end register (0x11).
+    write_indexed(
+        CRT_CONTROLLER,
+        0x03,
+        read_indexed(CRT_CONTROLLER, 0x03) | 0x80,
+    );
+    write_indexed(
+        CRT_CONTROLLER,
+        0x11,
+        read_indexed(CRT_CONTROLLER, 0x11) & !0x80,
+    );
+    for (index, &value) in registers.crt_controller.iter().enumerate() {
+        let value = match index {
+            0x03 => value | 0x80,
+            0x11 => value & !0x80,
+            _ => value,
+        };
+        write_indexed(CRT_CONTROLLER, index as u8, value);
+    }
+
+    for (index, &value) in registers.graphics_controller.iter().enumerate() {
+        write_indexed(GRAPHICS_CONTROLLER, index as u8, value);
+    }
+
+    unsafe {
+        let mut input_status = Port::<u8>::new(INPUT_STATUS);
+        let mut attribute_controller = Port::<u8>::new(ATTRIBUTE_CONTROLLER);
+
+        for (index, &value) in registers.attribute_controller.iter().enumerate() {
+            input_status.read();
+            attribute_controller.write(index as u8);
+            attribute_controller.write(value);
+        }
+
+        // Setting bit 5 of the index hands the palette back to the display, which unblanks it.
+        input_status.read();
+        attribute_controller.write(0x20);
+    }
+}
+
+/// CRTC cursor start, cursor end and cursor location high/low registers.
+const CURSOR_REGISTERS: [u8; 4] = [0x0a, 0x0b, 0x0e, 0x0f];
+
+///
+/// Register values defining a display mode. <br>
+/// The attribute controller palette is the identity in every mode, so palette
+/// entries 0-15 are the text colours and `load_default_palette` applies to all modes.
+///
+struct Registers {
+    misc: u8,
+    sequencer: [u8; 5],
+    crt_controller: [u8; 25],
+    graphics_controller: [u8; 9],
+    attribute_controller: [u8; 21],
+}
+
+static TEXT_80X25: Registers = Registers {
+    misc: 0x67,
+    sequencer: [0x03, 0x00, 0x03, 0x00, 0x02],
+    crt_controller: [
+        0x5f, 0x4f, 0x50, 0x82, 0x55, 0x81, 0xbf, 0x1f, 0x00, 0x4f, 0x0d, 0x0e, 0x00, 0x00, 0x00,
+        0x50, 0x9c, 0x0e, 0x8f, 0x28, 0x1f, 0x96, 0xb9, 0xa3, 0xff,
+    ],
+    graphics_controller: [0x00, 0x00, 0x00, 0x00, 0x00, 0x10, 0x0e, 0x00, 0xff],
+    attribute_controller: [
+        0x00, 0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0x07, 0x08, 0x09, 0x0a, 0x0b, 0x0c, 0x0d, 0x0e,
+        0x0f, 0x0c, 0x00, 0x0f, 0x08, 0x00,
+    ],
+};
+
+static MODE_13H: Registers = Registers {
+    misc: 0x63,
+    sequencer: [0x03, 0x01, 0x0f, 0x00, 0x0e], // Chain 4: one byte per pixel
+    crt_controller: [
+        0x5f, 0x4f, 0x50, 0x82, 0x54, 0x80, 0xbf, 0x1f, 0x00, 0x41, 0x00, 0x00, 0x00, 0x00, 0x00,
+        0x00, 0x9c, 0x0e, 0x8f, 0x28, 0x40, 0x96, 0xb9, 0xa3, 0xff,
+    ],
+    graphics_controller: [0x00, 0x00, 0x00, 0x00, 0x00, 0x40, 0x05, 0x0f, 0xff],
+    attribute_controller: [
+        0x00, 0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0x07, 0x08, 0x09, 0x0a, 0x0b, 0x0c, 0x0d, 0x0e,
+        0x0f, 0x41, 0x00, 0x0f, 0x00, 0x00,
+    ],
+};
+
+static MODE_12H: Registers = Registers {
+    misc: 0xe3,
+    sequencer: [0x03, 0x01, 0x0f, 0x00, 0x06], // All four planes writable
+    crt_controller: [
+        0x5f, 0x4f, 0x50, 0x82, 0x54, 0x80, 0x0b, 0x3e, 0x00, 0x40, 0x00, 0x00, 0x00, 0x00, 0x00,
+        0x00, 0xea, 0x0c, 0xdf, 0x28, 0x00, 0xe7, 0x04, 0xe3, 0xff,
+    ],
+    graphics_controller: [0x00, 0x00, 0x00, 0x00, 0x00, 0x02, 0x05, 0x0f, 0xff], // Write mode 2
+    attribute_controller: [
+        0x00, 0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0x07, 0x08, 0x09, 0x0a, 0x0b, 0x0c, 0x0d, 0x0e,
+        0x0f, 0x01, 0x00, 0x0f, 0x00, 0x00,
+    ],
+};
+
+#[test_case]
+fn test_default_palette() {
+    assert_eq!(default_palette(4), Color::Red.rgb());
+    assert_eq!(default_palette(15), Color::White.rgb());
+    assert_eq!(default_palette(16), 0x00_0000);
+    assert_eq!(default_palette(231), 0xff_ffff);
+    assert_eq!(default_palette(232), 0x08_0808);
+    assert_eq!(default_palette(255), 0xee_eeee);
+}
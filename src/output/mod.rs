@@ -1,2 +1,132 @@
+use core::{
+    fmt,
+    sync::atomic::{AtomicBool, Ordering},
+};
+use vga::{Color, ColorCode};
+
+pub mod cp437;
+pub mod framebuffer;
+pub mod graphics;
+pub mod psf;
 pub mod serial;
 pub mod vga;
+
+static SERIAL_MIRROR: AtomicBool = AtomicBool::new(false);
+
+/// Prints to the framebuffer console once initialized, otherwise the VGA text console,
+/// and to serial if mirroring is enabled.
+#[macro_export]
+macro_rules! print {
+    ($($arg:tt)*) => ($crate::output::_print(format_args!($($arg)*)));
+}
+
+/// Prints to the framebuffer console once initialized, otherwise the VGA text console, appending a newline.
+#[macro_export]
+macro_rules! println {
+    () => ($crate::print!("\n"));
+    ($($arg:tt)*) => ($crate::print!("{}\n", format_args!($($arg)*)));
+}
+
+///
+/// Prints to virtual console `$console`, e.g. `vga::active_console()`. <br>
+/// `print!` always writes to the kernel log console, this lets e.g. a shell use its own.
+///
+#[macro_export]
+macro_rules! console_print {
+    ($console:expr, $($arg:tt)*) => ($crate::output::_print_to($console, format_args!($($arg)*)));
+}
+
+/// Prints to virtual console `$console`, appending a newline.
+#[macro_export]
+macro_rules! console_println {
+    ($console:expr) => ($crate::console_print!($console, "\n"));
+    ($console:expr, $($arg:tt)*) => ($crate::console_print!($console, "{}\n", format_args!($($arg)*)));
+}
+
+///
+/// Also sends everything printed with `print!` to the serial interface, coloured with ANSI
+/// escape codes, so headless runs capture what the screen shows. Off until enabled at boot,
+/// which the kernel does if built with the `serial-mirror` feature, on by default.
+///
+pub fn mirror_to_serial(enabled: bool) {
+    SERIAL_MIRROR.store(enabled, Ordering::SeqCst);
+}
+
+#[doc(hidden)]
+pub fn _print(args: fmt::Arguments) {
+    print_with(vga::LOG_CONSOLE, None, args);
+}
+
+/// Prints to the same consoles as `print!`, temporarily switching the foreground colour.
+#[doc(hidden)]
+pub fn _print_colored(foreground: Color, args: fmt::Arguments) {
+    print_with(vga::LOG_CONSOLE, Some(foreground), args);
+}
+
+///
+/// Prints to virtual console `console` instead of the kernel log console. <br>
+/// The framebuffer console has no virtual consoles, so it receives the output of all of them.
+///
+#[doc(hidden)]
+pub fn _print_to(console: usize, args: fmt::Arguments) {
+    assert!(console < vga::CONSOLE_COUNT, "Console index out of range.");
+    print_with(console, None, args);
+}
+
+fn print_with(console: usize, foreground: Option<Color>, args: fmt::Arguments) {
+    use x86_64::instructions::interrupts;
+
+    // `without_interrupts` so interrupt handler output cannot interleave between screen and serial
+    interrupts::without_interrupts(|| {
+        let color_code = if framebuffer::is_initialized() {
+            framebuffer::with_writer(|writer| write_console(writer, foreground, args))
+        } else {
+            Some(write_console(
+                &mut *vga::CONSOLES[console].lock(),
+                foreground,
+                args,
+            ))
+        };
+
+        if let Some(color_code) = color_code {
+            if SERIAL_MIRROR.load(Ordering::SeqCst) {
+                serial::_print_colored(color_code, args);
+            }
+        }
+    });
+}
+
+/// A screen console `print!` can write to.
+trait Console: fmt::Write {
+    fn color_code(&mut self) -> &mut ColorCode;
+}
+
+impl Console for vga::Writer {
+    fn color_code(&mut self) -> &mut ColorCode {
+        &mut self.color_code
+    }
+}
+
+impl Console for framebuffer::FrameBufferWriter {
+    fn color_code(&mut self) -> &mut ColorCode {
+        &mut self.color_code
+    }
+}
+
+/// Writes `args` to `console`, in `foreground` if given, returning the colours used.
+fn write_console(
+    console: &mut impl Console,
+    foreground: Option<Color>,
+    args: fmt::Arguments,
+) -> ColorCode {
+    let previous = *console.color_code();
+    if let Some(foreground) = foreground {
+        console.color_code().foreground_color = foreground;
+    }
+    let color_code = *console.color_code();
+
+    console.write_fmt(args).unwrap();
+    *console.color_code() = previous;
+
+    color_code
+}
@@ -0,0 +1,234 @@
+use core::convert::TryInto;
+
+const PSF1_MAGIC: [u8; 2] = [0x36, 0x04];
+const PSF1_MODE_512: u8 = 0x01;
+const PSF1_MODE_HAS_TABLE: u8 = 0x02;
+const PSF1_MODE_HAS_SEQ: u8 = 0x04;
+const PSF1_SEPARATOR: u16 = 0xffff;
+const PSF1_START_SEQ: u16 = 0xfffe;
+
+const PSF2_MAGIC: u32 = 0x864a_b572;
+const PSF2_HAS_UNICODE_TABLE: u32 = 0x01;
+const PSF2_SEPARATOR: u8 = 0xff;
+const PSF2_START_SEQ: u8 = 0xfe;
+
+/// Glyphs looked up when a character has no glyph of its own, in order of preference.
+const REPLACEMENT_CHARS: [char; 3] = ['\u{fffd}', '■', '?'];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FontError {
+    BadMagic,
+    Truncated,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum UnicodeTable {
+    None,
+    Psf1(&'static [u8]),
+    Psf2(&'static [u8]),
+}
+
+///
+/// A PC Screen Font (PSF1 or PSF2) bitmap font. <br>
+/// Each glyph is `height` rows of `bytes_per_row` bytes, most significant bit leftmost.
+///
+#[derive(Debug, Clone, Copy)]
+pub struct Font {
+    glyphs: &'static [u8],
+    glyph_count: usize,
+    bytes_per_glyph: usize,
+    pub width: usize,
+    pub height: usize,
+    unicode_table: UnicodeTable,
+    ascii_glyphs: [u16; 128], // Cached glyph indices for the hot ASCII path
+    replacement_glyph: usize,
+}
+
+impl Font {
+    /// Parses a PSF1 or PSF2 font from `data`.
+    pub fn parse(data: &'static [u8]) -> Result<Font, FontError> {
+        let mut font = if data.starts_with(&PSF1_MAGIC) {
+            Self::parse_psf1(data)?
+        } else if read_u32(data, 0)? == PSF2_MAGIC {
+            Self::parse_psf2(data)?
+        } else {
+            return Err(FontError::BadMagic);
+        };
+
+        font.replacement_glyph = REPLACEMENT_CHARS
+            .iter()
+            .find_map(|&c| font.lookup(c))
+            .unwrap_or(0);
+
+        for (c, glyph) in font.ascii_glyphs.iter_mut().enumerate() {
+            *glyph = font
+                .lookup(c as u8 as char)
+                .unwrap_or(font.replacement_glyph) as u16;
+        }
+
+        Ok(font)
+    }
+
+    fn parse_psf1(data: &'static [u8]) -> Result<Font, FontError> {
+        let mode = *data.get(2).ok_or(FontError::Truncated)?;
+        let height = *data.get(3).ok_or(FontError::Truncated)? as usize;
+        let glyph_count = if mode & PSF1_MODE_512 != 0 { 512 } else { 256 };
+        let glyphs_end = 4 + glyph_count * height;
+
+        let glyphs = data.get(4..glyphs_end).ok_or(FontError::Truncated)?;
+        let unicode_table = if mode & (PSF1_MODE_HAS_TABLE | PSF1_MODE_HAS_SEQ) != 0 {
+            UnicodeTable::Psf1(&data[glyphs_end..])
+        } else {
+            UnicodeTable::None
+        };
+
+        Ok(Font {
+            glyphs,
+            glyph_count,
+            bytes_per_glyph: height,
+            width: 8,
+            height,
+            unicode_table,
+            ascii_glyphs: [0; 128],
+            replacement_glyph: 0,
+        })
+    }
+
+    fn parse_psf2(data: &'static [u8]) -> Result<Font, FontError> {
+        let header_size = read_u32(data, 8)? as usize;
+        let flags = read_u32(data, 12)?;
+        let glyph_count = read_u32(data, 16)? as usize;
+        let bytes_per_glyph = read_u32(data, 20)? as usize;
+        let height = read_u32(data, 24)? as usize;
+        let width = read_u32(data, 28)? as usize;
+        let glyphs_end = header_size + glyph_count * bytes_per_glyph;
+
+        let glyphs = data
+            .get(header_size..glyphs_end)
+            .ok_or(FontError::Truncated)?;
+        let unicode_table = if flags & PSF2_HAS_UNICODE_TABLE != 0 {
+            UnicodeTable::Psf2(&data[glyphs_end..])
+        } else {
+            UnicodeTable::None
+        };
+
+        Ok(Font {
+            glyphs,
+            glyph_count,
+            bytes_per_glyph,
+            width,
+            height,
+            unicode_table,
+            ascii_glyphs: [0; 128],
+            replacement_glyph: 0,
+        })
+    }
+
+    /// Number of bytes making up a single row of a glyph.
+    pub fn bytes_per_row(&self) -> usize {
+        (self.width + 7) / 8
+    }
+
+    /// Returns the bitmap for `character`, or the replacement glyph if the font has none.
+    pub fn glyph(&self, character: char) -> &'static [u8] {
+        let index = match character {
+            '\0'..='\u{7f}' => self.ascii_glyphs[character as usize] as usize,
+            _ => self.lookup(character).unwrap_or(self.replacement_glyph),
+        };
+
+        let start = index * self.bytes_per_glyph;
+        &self.glyphs[start..start + self.bytes_per_glyph]
+    }
+
+    /// Finds the glyph index for `character` by walking the unicode table, if there is one.
+    fn lookup(&self, character: char) -> Option<usize> {
+        match self.unicode_table {
+            UnicodeTable::None => {
+                let index = character as usize;
+                (index < self.glyph_count).then_some(index)
+            }
+            UnicodeTable::Psf1(table) => {
+                let mut glyph = 0;
+                let mut in_sequence = false;
+
+                for entry in table.chunks_exact(2) {
+                    match u16::from_le_bytes([entry[0], entry[1]]) {
+                        PSF1_SEPARATOR => {
+                            glyph += 1;
+                            in_sequence = false;
+                        }
+                        PSF1_START_SEQ => in_sequence = true,
+                        // Sequences of combining characters are not rendered.
+                        value if !in_sequence && u32::from(value) == character as u32 => {
+                            return (glyph < self.glyph_count).then_some(glyph);
+                        }
+                        _ => {}
+                    }
+                }
+
+                None
+            }
+            UnicodeTable::Psf2(table) => table
+                .split(|&byte| byte == PSF2_SEPARATOR)
+                .take(self.glyph_count)
+                .position(|entry| {
+                    // Single characters come first; sequences after `PSF2_START_SEQ` are not rendered.
+                    let singles = entry.split(|&byte| byte == PSF2_START_SEQ).next();
+                    singles
+                        .and_then(|bytes| core::str::from_utf8(bytes).ok())
+                        .map_or(false, |s| s.chars().any(|c| c == character))
+                }),
+        }
+    }
+}
+
+fn read_u32(data: &[u8], offset: usize) -> Result<u32, FontError> {
+    let bytes = data
+        .get(offset..offset + 4)
+        .ok_or(FontError::Truncated)?
+        .try_into()
+        .map_err(|_| FontError::Truncated)?;
+    Ok(u32::from_le_bytes(bytes))
+}
+
+#[test_case]
+fn test_psf2_parse() {
+    let font = Font::parse(include_bytes!("fonts/fixed-8x16.psf")).expect("Failed to parse font.");
+    assert_eq!((font.width, font.height), (8, 16));
+
+    let replacement = font.glyph('\u{fffd}');
+    assert!(font.glyph('A').iter().any(|&row| row != 0));
+    assert_ne!(font.glyph('A'), font.glyph('B'));
+    assert_ne!(font.glyph('é'), replacement);
+    assert_eq!(font.glyph('日'), replacement);
+}
+
+#[test_case]
+fn test_psf1_parse() {
+    // Two 8x2 glyphs with a unicode table mapping 'x' to the second.
+    static DATA: [u8; 524] = {
+        let mut data = [0; 524];
+        data[0] = PSF1_MAGIC[0];
+        data[1] = PSF1_MAGIC[1];
+        data[2] = PSF1_MODE_HAS_TABLE;
+        data[3] = 2;
+        data[4 + 2] = 0xff; // Glyph 1, row 0
+        data[516] = 0xff; // Glyph 0: no mappings
+        data[517] = 0xff;
+        data[518] = b'x'; // Glyph 1: 'x'
+        data[519] = 0;
+        data[520] = 0xff;
+        data[521] = 0xff;
+        data
+    };
+
+    let font = Font::parse(&DATA).expect("Failed to parse font.");
+    assert_eq!((font.width, font.height), (8, 2));
+    assert_eq!(font.glyph('x'), &[0xff, 0]);
+    assert_eq!(font.glyph('y'), &[0, 0]);
+}
+
+#[test_case]
+fn test_psf_bad_magic() {
+    assert_eq!(Font::parse(&[0; 32]).err(), Some(FontError::BadMagic));
+}
@@ -1,27 +1,64 @@
+use super::vga::{Color, ColorCode};
+use crate::sync::IrqSafeMutex;
 use lazy_static::lazy_static;
-use spin::Mutex;
 use uart_16550::SerialPort;
 
 lazy_static! {
-    pub static ref SERIAL1: Mutex<SerialPort> = {
+    pub static ref SERIAL1: IrqSafeMutex<SerialPort> = {
         let mut serial_port = unsafe { SerialPort::new(0x3f8) };    // 0x3f8 -> standard port for first serial interface
         serial_port.init();
-        Mutex::new(serial_port)
+        IrqSafeMutex::new(serial_port)
     };
 }
 
 #[doc(hidden)]
 pub fn _print(args: ::core::fmt::Arguments) {
     use core::fmt::Write;
-    use x86_64::instructions::interrupts;
 
-    // `without_interrupts` to prevent deadlocks
-    interrupts::without_interrupts(|| {
-        SERIAL1
-            .lock()
-            .write_fmt(args)
-            .expect("Failed to print to serial.");
-    });
+    SERIAL1
+        .lock()
+        .write_fmt(args)
+        .expect("Failed to print to serial.");
+}
+
+///
+/// Prints to the serial interface in `color_code`, using ANSI escape codes. <br>
+/// Output in the default colours is sent as is to keep plain logs readable.
+///
+#[doc(hidden)]
+pub fn _print_colored(color_code: ColorCode, args: ::core::fmt::Arguments) {
+    if color_code == ColorCode::default() {
+        _print(args);
+    } else {
+        _print(format_args!(
+            "\x1b[{};{}m{}\x1b[0m",
+            ansi_code(color_code.foreground_color),
+            ansi_code(color_code.background_color) + 10,
+            args
+        ));
+    }
+}
+
+/// Returns the ANSI foreground colour code for `color`, background codes being 10 higher.
+fn ansi_code(color: Color) -> u8 {
+    match color {
+        Color::Black => 30,
+        Color::Red => 31,
+        Color::Green => 32,
+        Color::Brown => 33,
+        Color::Blue => 34,
+        Color::Magenta => 35,
+        Color::Cyan => 36,
+        Color::LightGray => 37,
+        Color::DarkGray => 90,
+        Color::LightRed => 91,
+        Color::LightGreen => 92,
+        Color::Yellow => 93,
+        Color::LightBlue => 94,
+        Color::Pink => 95,
+        Color::LightCyan => 96,
+        Color::White => 97,
+    }
 }
 
 /// Prints to the host console through the serial interface.
@@ -1,42 +1,106 @@
 #![allow(dead_code)]
 
-use core::fmt;
+use super::cp437;
+use crate::sync::IrqSafeMutex;
+use alloc::collections::VecDeque;
+use core::{
+    fmt, ptr,
+    sync::atomic::{AtomicUsize, Ordering},
+};
 use lazy_static::lazy_static;
-use spin::Mutex;
-use volatile::Volatile;
 
 const BUFFER_HEIGHT: usize = 25;
 const BUFFER_WIDTH: usize = 80;
 const TAB_WIDTH: usize = 4;
 
-#[macro_export]
-macro_rules! print {
-    ($($arg:tt)*) => ($crate::output::vga::_print(format_args!($($arg)*)));
+/// Number of independent virtual consoles, switchable with Alt+F1..F6.
+pub const CONSOLE_COUNT: usize = 6;
+
+/// The console `print!` and `println!` write to, e.g. for the kernel log.
+pub const LOG_CONSOLE: usize = 0;
+
+/// Number of lines of history kept per console once scrollback is enabled after heap initialization.
+pub const DEFAULT_SCROLLBACK_LINES: usize = 40;
+
+/// Scrolls the active console back through its history by `lines` lines.
+pub fn scroll_up(lines: usize) {
+    CONSOLES[active_console()].lock().scroll_up(lines);
 }
 
-#[macro_export]
-macro_rules! println {
-    () => ($crate::print!("\n"));
-    ($($arg:tt)*) => ($crate::print!("{}\n", format_args!($($arg)*)));
+/// Scrolls the active console forwards towards the live output by `lines` lines.
+pub fn scroll_down(lines: usize) {
+    CONSOLES[active_console()].lock().scroll_down(lines);
 }
 
-#[doc(hidden)]
-pub fn _print(args: fmt::Arguments) {
-    use core::fmt::Write;
+/// Enables scrollback with `lines` lines of history on every console.
+pub fn enable_scrollback(lines: usize) {
+    for console in CONSOLES.iter() {
+        console.lock().enable_scrollback(lines);
+    }
+}
+
+/// Returns the index of the console currently shown on screen.
+pub fn active_console() -> usize {
+    ACTIVE_CONSOLE.load(Ordering::SeqCst)
+}
+
+///
+/// Shows console `index` on screen. <br>
+/// Other consoles keep receiving output in the background.
+///
+pub fn switch_console(index: usize) {
     use x86_64::instructions::interrupts;
 
-    // `without_interrupts` to prevent deadlocks
+    assert!(index < CONSOLE_COUNT, "Console index out of range.");
+
     interrupts::without_interrupts(|| {
-        WRITER.lock().write_fmt(args).unwrap();
+        let previous = active_console();
+        if previous == index {
+            return;
+        }
+
+        // Hide the previous console before showing the next so only one ever touches the buffer.
+        CONSOLES[previous].lock().set_visible(false);
+        ACTIVE_CONSOLE.store(index, Ordering::SeqCst);
+        CONSOLES[index].lock().set_visible(true);
     });
 }
 
+/// Copies the active console back to the text buffer, e.g. after leaving a graphics mode.
+pub(super) fn redraw() {
+    CONSOLES[active_console()].lock().redraw();
+}
+
+static ACTIVE_CONSOLE: AtomicUsize = AtomicUsize::new(0);
+
+///
+/// The memory-mapped VGA text buffer. <br>
+/// Only accessed through volatile reads and writes of single characters, never through references.
+///
+const HARDWARE_BUFFER: *mut Buffer = 0xb8000 as *mut Buffer;
+
 ///
 /// An two-dimensional array representing the .
 ///
 #[repr(transparent)]
 struct Buffer {
-    content: [[Volatile<ScreenChar>; BUFFER_WIDTH]; BUFFER_HEIGHT],
+    content: [[ScreenChar; BUFFER_WIDTH]; BUFFER_HEIGHT],
+}
+
+impl Buffer {
+    ///
+    /// Writes `character` to the hardware buffer at `row`, `col`. <br>
+    /// This function is unsafe as it is up to the caller to ensure that it holds the lock of the
+    /// visible console, the only one drawing to the screen.
+    ///
+    unsafe fn write(row: usize, col: usize, character: ScreenChar) {
+        ptr::addr_of_mut!((*HARDWARE_BUFFER).content[row][col]).write_volatile(character);
+    }
+
+    /// Reads the character shown at `row`, `col`.
+    fn read(row: usize, col: usize) -> ScreenChar {
+        unsafe { ptr::addr_of!((*HARDWARE_BUFFER).content[row][col]).read_volatile() }
+    }
 }
 
 ///
@@ -62,6 +126,37 @@ pub enum Color {
     White,
 }
 
+impl Color {
+    /// Returns the colour's standard VGA palette value as `0xRRGGBB`.
+    pub fn rgb(self) -> u32 {
+        match self {
+            Color::Black => 0x00_0000,
+            Color::Blue => 0x00_00aa,
+            Color::Green => 0x00_aa00,
+            Color::Cyan => 0x00_aaaa,
+            Color::Red => 0xaa_0000,
+            Color::Magenta => 0xaa_00aa,
+            Color::Brown => 0xaa_5500,
+            Color::LightGray => 0xaa_aaaa,
+            Color::DarkGray => 0x55_5555,
+            Color::LightBlue => 0x55_55ff,
+            Color::LightGreen => 0x55_ff55,
+            Color::LightCyan => 0x55_ffff,
+            Color::LightRed => 0xff_5555,
+            Color::Pink => 0xff_55ff,
+            Color::Yellow => 0xff_ff55,
+            Color::White => 0xff_ffff,
+        }
+    }
+}
+
+/// Palette index of the colour, as used by the graphics modes in `output::graphics`.
+impl From<Color> for u8 {
+    fn from(color: Color) -> u8 {
+        color as u8
+    }
+}
+
 ///
 /// Some information used to draw a  to the screen.
 ///
@@ -74,6 +169,16 @@ pub struct ColorCode {
     pub background_color: Color,
 }
 
+/// White on black, the colours consoles start with.
+impl Default for ColorCode {
+    fn default() -> Self {
+        ColorCode {
+            foreground_color: Color::White,
+            background_color: Color::Black,
+        }
+    }
+}
+
 impl ColorCode {
     pub fn get_value(&self, foreground: Option<Color>, background: Option<Color>) -> u8 {
         (background.unwrap_or(self.background_color) as u8) << 4
@@ -94,33 +199,171 @@ struct ScreenChar {
     color_code: u8,
 }
 
+const BLANK_CHAR: ScreenChar = ScreenChar {
+    ascii_character: 0,
+    color_code: 0,
+};
+
+type Row = [ScreenChar; BUFFER_WIDTH];
+
+///
+/// Lines that have scrolled off the top of the screen. <br>
+/// Storage is only reserved once `capacity` is set, as the heap is not available at boot.
+///
+struct Scrollback {
+    lines: VecDeque<Row>,
+    capacity: usize,
+    offset: usize, // How many lines the view is scrolled back from the live output
+}
+
+impl Scrollback {
+    fn new() -> Self {
+        Scrollback {
+            lines: VecDeque::new(),
+            capacity: 0,
+            offset: 0,
+        }
+    }
+
+    fn push(&mut self, row: Row) {
+        if self.capacity == 0 {
+            return;
+        }
+
+        // Reuse the oldest slot once full so that pushing never allocates.
+        if self.lines.len() >= self.capacity {
+            self.lines.pop_front();
+        }
+        self.lines.push_back(row);
+    }
+}
+
 ///
 /// Writer struct to interact with a VGA Buffer.
 ///
+/// Each virtual console owns a `Writer`. Its live screen is kept in `screen`
+/// and only mirrored to the VGA buffer while the console is `visible`, which
+/// also allows the view to be restored after scrolling back through `scrollback`.
+///
 pub struct Writer {
-    buffer: &'static mut Buffer,
+    screen: [Row; BUFFER_HEIGHT],
+    scrollback: Scrollback,
+    visible: bool,
     pub color_code: ColorCode,
     pub cursor_position: (usize, usize),
 }
 
 lazy_static! {
-    pub static ref WRITER: Mutex<Writer> = Mutex::new(Writer {
-        buffer: unsafe { &mut *(0xb8000 as *mut Buffer) },
-        color_code: ColorCode { foreground_color: Color::White, background_color: Color::Black },
-        cursor_position: (0, 0) // x, y | col, row
-    });
+    pub static ref CONSOLES: [IrqSafeMutex<Writer>; CONSOLE_COUNT] =
+        core::array::from_fn(|index| IrqSafeMutex::new(Writer::new(index == 0)));
+
+    /// The kernel log console, targeted by `print!` and `println!`.
+    pub static ref WRITER: &'static IrqSafeMutex<Writer> = &CONSOLES[LOG_CONSOLE];
 }
 
 impl Writer {
+    fn new(visible: bool) -> Self {
+        Writer {
+            screen: [[BLANK_CHAR; BUFFER_WIDTH]; BUFFER_HEIGHT],
+            scrollback: Scrollback::new(),
+            visible,
+            color_code: ColorCode::default(),
+            cursor_position: (0, 0), // x, y | col, row
+        }
+    }
+
+    fn set_visible(&mut self, visible: bool) {
+        self.visible = visible;
+        self.redraw();
+    }
+
+    ///
+    /// Keeps up to `lines` lines of history once they scroll off the screen. <br>
+    /// Requires the heap to be initialized; a value of 0 disables scrollback.
+    ///
+    pub fn enable_scrollback(&mut self, lines: usize) {
+        let scrollback = &mut self.scrollback;
+        while scrollback.lines.len() > lines {
+            scrollback.lines.pop_front();
+        }
+
+        scrollback.lines.shrink_to_fit();
+        scrollback
+            .lines
+            .reserve_exact(lines - scrollback.lines.len());
+        scrollback.capacity = lines;
+        scrollback.offset = scrollback.offset.min(scrollback.lines.len());
+        self.redraw();
+    }
+
+    /// Moves the view `lines` lines back into the scrollback history.
+    pub fn scroll_up(&mut self, lines: usize) {
+        let max_offset = self.scrollback.lines.len();
+        let offset = (self.scrollback.offset + lines).min(max_offset);
+
+        if offset != self.scrollback.offset {
+            self.scrollback.offset = offset;
+            self.redraw();
+        }
+    }
+
+    /// Moves the view `lines` lines towards the live output.
+    pub fn scroll_down(&mut self, lines: usize) {
+        let offset = self.scrollback.offset.saturating_sub(lines);
+
+        if offset != self.scrollback.offset {
+            self.scrollback.offset = offset;
+            self.redraw();
+        }
+    }
+
+    /// Returns the view to the live output if it is scrolled back.
+    pub fn scroll_to_bottom(&mut self) {
+        self.scroll_down(self.scrollback.offset);
+    }
+
+    /// Copies the currently viewed lines to the VGA buffer.
+    fn redraw(&mut self) {
+        if !self.visible {
+            return;
+        }
+
+        let history = self.scrollback.lines.len();
+        let top = history - self.scrollback.offset;
+
+        for row in 0..BUFFER_HEIGHT {
+            let line = top + row;
+            let content = if line < history {
+                &self.scrollback.lines[line]
+            } else {
+                &self.screen[line - history]
+            };
+
+            for (col, character) in content.iter().enumerate() {
+                // Only the visible console redraws, and its lock is held through `&mut self`.
+                unsafe { Buffer::write(row, col, *character) };
+            }
+        }
+    }
+
+    fn put_char(&mut self, row: usize, col: usize, character: ScreenChar) {
+        self.screen[row][col] = character;
+        if self.visible {
+            unsafe { Buffer::write(row, col, character) };
+        }
+    }
+
     fn clear_row(&mut self, row: usize) {
+        let blank = ScreenChar {
+            ascii_character: 0,
+            color_code: self.color_code.get_value(
+                Some(self.color_code.background_color),
+                Some(self.color_code.background_color),
+            ),
+        };
+
         for col in 0..BUFFER_WIDTH {
-            self.buffer.content[row][col].write(ScreenChar {
-                ascii_character: 0,
-                color_code: self.color_code.get_value(
-                    Some(self.color_code.background_color),
-                    Some(self.color_code.background_color),
-                ),
-            })
+            self.put_char(row, col, blank);
         }
     }
 
@@ -130,10 +373,12 @@ impl Writer {
         if (self.cursor_position.1 + 1) < BUFFER_HEIGHT {
             self.cursor_position.1 += 1;
         } else {
+            self.scrollback.push(self.screen[0]);
+
             for row in 0..BUFFER_HEIGHT - 1 {
                 for col in 0..BUFFER_WIDTH {
-                    let character = self.buffer.content[row + 1][col].read();
-                    self.buffer.content[row][col].write(character);
+                    let character = self.screen[row + 1][col];
+                    self.put_char(row, col, character);
                 }
             }
 
@@ -144,34 +389,49 @@ impl Writer {
 
     pub fn write_byte(&mut self, byte: u8) {
         match byte {
-            b'\n' => self.new_line(),
+            b'\n' => {
+                // New output always snaps the view back to the live screen.
+                self.scroll_to_bottom();
+                self.new_line();
+            }
             b'\t' => {
                 for _ in 0..TAB_WIDTH {
-                    self.write_byte(b' ');
+                    self.write_glyph(b' ');
                 }
             }
-            _ => {
-                if self.cursor_position.0 >= BUFFER_WIDTH {
-                    self.new_line();
-                }
+            _ => self.write_glyph(byte),
+        }
+    }
 
-                let row = self.cursor_position.1;
-                let col = self.cursor_position.0;
+    /// Draws the code page 437 glyph `glyph` at the cursor without interpreting control characters.
+    pub fn write_glyph(&mut self, glyph: u8) {
+        // New output always snaps the view back to the live screen.
+        self.scroll_to_bottom();
 
-                self.buffer.content[row][col].write(ScreenChar {
-                    ascii_character: byte,
-                    color_code: self.color_code.get_value(None, None),
-                });
-                self.cursor_position.0 += 1;
-            }
+        if self.cursor_position.0 >= BUFFER_WIDTH {
+            self.new_line();
         }
+
+        let row = self.cursor_position.1;
+        let col = self.cursor_position.0;
+
+        let character = ScreenChar {
+            ascii_character: glyph,
+            color_code: self.color_code.get_value(None, None),
+        };
+        self.put_char(row, col, character);
+        self.cursor_position.0 += 1;
     }
 
     pub fn write_string(&mut self, s: &str) {
-        for byte in s.bytes() {
-            match byte {
-                0x20..=0x7e | b'\n' | b'\t' => self.write_byte(byte), // Printable ASCII bytes | newline character
-                _ => self.write_byte(0xfe), // Non-printables -> prints "■"
+        for character in s.chars() {
+            match character {
+                '\n' | '\t' => self.write_byte(character as u8),
+                _ => {
+                    // Unmappable characters -> prints "■"
+                    let glyph = cp437::from_char(character).unwrap_or(cp437::REPLACEMENT);
+                    self.write_glyph(glyph);
+                }
             }
         }
     }
@@ -210,8 +470,63 @@ fn test_println_output() {
         writeln!(writer, "\n{}", s).expect("Failed to write to line.");
 
         for (i, chr) in s.chars().enumerate() {
-            let screen_char = writer.buffer.content[row - 1][i].read();
+            let screen_char = Buffer::read(row - 1, i);
             assert_eq!(char::from(screen_char.ascii_character), chr);
         }
     });
 }
+
+#[test_case]
+fn test_println_cp437_output() {
+    use core::fmt::Write;
+    use x86_64::instructions::interrupts;
+
+    interrupts::without_interrupts(|| {
+        let mut writer = WRITER.lock();
+
+        writeln!(writer, "\nü╔π€").expect("Failed to write to line.");
+        let row = writer.cursor_position.1 - 1;
+
+        let glyphs: [u8; 4] = [0x81, 0xc9, 0xe3, cp437::REPLACEMENT];
+        for (i, &glyph) in glyphs.iter().enumerate() {
+            let screen_char = Buffer::read(row, i);
+            assert_eq!(screen_char.ascii_character, glyph);
+        }
+    });
+}
+
+#[test_case]
+fn test_virtual_console_switching() {
+    use core::fmt::Write;
+    use x86_64::instructions::interrupts;
+
+    interrupts::without_interrupts(|| {
+        let row = {
+            let mut console = CONSOLES[1].lock();
+            writeln!(console, "\nX").expect("Failed to write to line.");
+            console.cursor_position.1 - 1
+        };
+
+        // Background consoles must not draw to the screen.
+        assert_ne!(Buffer::read(row, 0).ascii_character, b'X');
+
+        switch_console(1);
+        assert_eq!(active_console(), 1);
+        assert_eq!(Buffer::read(row, 0).ascii_character, b'X');
+
+        switch_console(0);
+        assert_eq!(active_console(), 0);
+    });
+}
+
+#[test_case]
+fn test_console_print() {
+    use x86_64::instructions::interrupts;
+
+    crate::console_println!(2, "\nY");
+    interrupts::without_interrupts(|| {
+        let console = CONSOLES[2].lock();
+        let row = console.cursor_position.1 - 1;
+        assert_eq!(console.screen[row][0].ascii_character, b'Y');
+    });
+}
@@ -0,0 +1,102 @@
+use crate::{
+    logging::{self, ring::MessageBuffer},
+    memory::paging,
+    output::{framebuffer, serial::SERIAL1, vga::CONSOLES},
+};
+use core::{
+    fmt::Write,
+    panic::PanicInfo,
+    sync::atomic::{AtomicBool, AtomicU64, Ordering},
+};
+use uart_16550::SerialPort;
+use x86_64::VirtAddr;
+
+static PANICKING: AtomicBool = AtomicBool::new(false);
+static FAULT_ADDRESS: AtomicU64 = AtomicU64::new(NO_FAULT);
+const NO_FAULT: u64 = u64::MAX; // Not a canonical address
+
+///
+/// Makes the normal output paths usable from a panic handler. <br>
+/// Disables interrupts and releases the console, serial and logger locks, which the panicking
+/// code may have been holding. A panic raised while handling a panic only writes a short note
+/// straight to the serial port and halts, as the first report evidently failed.
+///
+pub fn prepare(info: &PanicInfo) {
+    x86_64::instructions::interrupts::disable();
+
+    if PANICKING.swap(true, Ordering::SeqCst) {
+        // 0x3f8 -> standard port for first serial interface, already initialized by SERIAL1
+        let mut serial_port = unsafe { SerialPort::new(0x3f8) };
+        let _ = writeln!(serial_port, "\n! === PANIC WHILE PANICKING === !");
+        if let Some(location) = info.location() {
+            let _ = writeln!(serial_port, " Location: {}", location);
+        }
+
+        crate::hlt_loop();
+    }
+
+    // Nothing else runs once interrupts are disabled, so any holder of these locks is the
+    // code that panicked, and it will never release them.
+    unsafe {
+        for console in CONSOLES.iter() {
+            console.force_unlock();
+        }
+        SERIAL1.force_unlock();
+        framebuffer::force_unlock();
+        logging::force_unlock();
+    }
+}
+
+///
+/// Records the page fault address behind an exception that is about to panic. <br>
+/// Fault handlers call this instead of logging, which could block on a lock held by the
+/// faulting code; `log_details` prints the address's translation path once locks are released.
+///
+pub fn record_fault_address(address: VirtAddr) {
+    FAULT_ADDRESS.store(address.as_u64(), Ordering::SeqCst);
+}
+
+/// Logs the location and message of the panic, and the translation path of a recorded fault address.
+pub fn log_details(info: &PanicInfo) {
+    match info.location() {
+        Some(location) => log::error!(
+            " Location: {}[{}:{}]",
+            location.file(),
+            location.line(),
+            location.column()
+        ),
+        None => log::error!(" Location: unknown"),
+    }
+
+    match info.message() {
+        Some(message) => log::error!(" Message: {:#?}", message),
+        None => match info.payload().downcast_ref::<&str>() {
+            Some(payload) => log::error!(" Payload: {:#?}", payload),
+            None => log::error!(" Message: none"),
+        },
+    }
+
+    let address = FAULT_ADDRESS.load(Ordering::SeqCst);
+    if address != NO_FAULT {
+        match paging::translation_path(VirtAddr::new(address)) {
+            Some(path) => log::error!(" Fault: {}", path),
+            None => log::error!(" Fault: {:#x}", address),
+        }
+    }
+}
+
+///
+/// Returns `true` if the panic message contains `text`, e.g. for tests expecting a panic. <br>
+/// Only the first 256 bytes of the message are searched, as it is formatted without allocating.
+///
+pub fn message_contains(info: &PanicInfo, text: &str) -> bool {
+    let mut message = MessageBuffer::new();
+    if let Some(arguments) = info.message() {
+        let _ = message.write_fmt(*arguments);
+    }
+
+    message
+        .as_bytes()
+        .windows(text.len().max(1))
+        .any(|window| window == text.as_bytes())
+}
@@ -0,0 +1,75 @@
+use x86_64::instructions::port::Port;
+
+const CONFIG_ADDRESS: u16 = 0xcf8;
+const CONFIG_DATA: u16 = 0xcfc;
+
+const VENDOR_NONE: u16 = 0xffff;
+
+///
+/// A function on the PCI bus, addressed through configuration mechanism #1.
+///
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PciDevice {
+    pub bus: u8,
+    pub device: u8,
+    pub function: u8,
+}
+
+impl PciDevice {
+    /// Reads the 32-bit configuration register at `offset` (must be 4-byte aligned).
+    pub fn read_config(&self, offset: u8) -> u32 {
+        let address = 1 << 31 // Enable bit
+            | u32::from(self.bus) << 16
+            | u32::from(self.device) << 11
+            | u32::from(self.function) << 8
+            | u32::from(offset & 0xfc);
+
+        unsafe {
+            Port::new(CONFIG_ADDRESS).write(address);
+            Port::new(CONFIG_DATA).read()
+        }
+    }
+
+    pub fn vendor_id(&self) -> u16 {
+        self.read_config(0x00) as u16
+    }
+
+    pub fn device_id(&self) -> u16 {
+        (self.read_config(0x00) >> 16) as u16
+    }
+
+    /// Returns the memory address held in base address register `index`, ignoring its flag bits.
+    pub fn memory_bar(&self, index: u8) -> u32 {
+        self.read_config(0x10 + index * 4) & !0xf
+    }
+}
+
+/// Scans every bus for the first function matching `vendor_id` and `device_id`.
+pub fn find_device(vendor_id: u16, device_id: u16) -> Option<PciDevice> {
+    for bus in 0..=255 {
+        for device in 0..32 {
+            for function in 0..8 {
+                let candidate = PciDevice {
+                    bus,
+                    device,
+                    function,
+                };
+
+                let vendor = candidate.vendor_id();
+                if vendor == VENDOR_NONE {
+                    // A missing function 0 means the whole device slot is empty.
+                    if function == 0 {
+                        break;
+                    }
+                    continue;
+                }
+
+                if vendor == vendor_id && candidate.device_id() == device_id {
+                    return Some(candidate);
+                }
+            }
+        }
+    }
+
+    None
+}
@@ -1,14 +1,19 @@
-use crate::interrupts::{InterruptIndex, PICS};
+use crate::{
+    interrupts::{InterruptIndex, PICS},
+    sync::IrqSafeMutex,
+};
 use lazy_static::lazy_static;
 use pc_keyboard::{layouts, HandleControl, Keyboard, ScancodeSet1};
-use spin::Mutex;
 use x86_64::{instructions::port::Port, structures::idt::InterruptStackFrame};
 
 pub extern "x86-interrupt" fn int_keyboard_handler(_stack_frame: InterruptStackFrame) {
     lazy_static! {
-        static ref KEYBOARD: Mutex<Keyboard<layouts::Us104Key, ScancodeSet1>> = Mutex::new(
-            Keyboard::new(layouts::Us104Key, ScancodeSet1, HandleControl::Ignore)
-        );
+        static ref KEYBOARD: IrqSafeMutex<Keyboard<layouts::Us104Key, ScancodeSet1>> =
+            IrqSafeMutex::new(Keyboard::new(
+                layouts::Us104Key,
+                ScancodeSet1,
+                HandleControl::Ignore
+            ));
     }
 
     let mut port = Port::new(0x60);
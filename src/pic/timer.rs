@@ -1,10 +1,45 @@
-use crate::print;
-
 use crate::interrupts::{InterruptIndex, PICS};
-use x86_64::structures::idt::InterruptStackFrame;
+use core::{
+    sync::atomic::{AtomicU64, Ordering},
+    time::Duration,
+};
+use x86_64::{instructions::port::Port, structures::idt::InterruptStackFrame};
+
+/// Rate at which the PIT raises timer interrupts.
+pub const TIMER_FREQUENCY: u64 = 100; // Hz
+
+const PIT_BASE_FREQUENCY: u64 = 1_193_182; // Hz
+const PIT_CHANNEL_0: u16 = 0x40;
+const PIT_COMMAND: u16 = 0x43;
+const PIT_SQUARE_WAVE_CHANNEL_0: u8 = 0x36; // Channel 0, lobyte/hibyte access, mode 3
+
+static TICKS: AtomicU64 = AtomicU64::new(0);
+
+/// Programs the PIT to interrupt at `TIMER_FREQUENCY`.
+pub fn init() {
+    let divisor = (PIT_BASE_FREQUENCY / TIMER_FREQUENCY) as u16;
+
+    unsafe {
+        Port::new(PIT_COMMAND).write(PIT_SQUARE_WAVE_CHANNEL_0);
+
+        let mut channel_0 = Port::new(PIT_CHANNEL_0);
+        channel_0.write(divisor as u8);
+        channel_0.write((divisor >> 8) as u8);
+    }
+}
+
+/// Number of timer interrupts since boot.
+pub fn ticks() -> u64 {
+    TICKS.load(Ordering::Relaxed)
+}
+
+/// Time since the timer was started, with a resolution of one tick.
+pub fn uptime() -> Duration {
+    Duration::from_millis(ticks() * 1000 / TIMER_FREQUENCY)
+}
 
 pub extern "x86-interrupt" fn int_timer_handler(_stack_frame: InterruptStackFrame) {
-    print!(".");
+    TICKS.fetch_add(1, Ordering::Relaxed);
 
     unsafe {
         PICS.lock()
@@ -0,0 +1,144 @@
+use core::{
+    mem::ManuallyDrop,
+    ops::{Deref, DerefMut},
+    sync::atomic::{AtomicBool, AtomicUsize, Ordering},
+};
+use x86_64::instructions::interrupts;
+
+/// Number of `IrqSafeMutex` guards alive, per-CPU state as the kernel runs on a single core.
+static HELD_GUARDS: AtomicUsize = AtomicUsize::new(0);
+/// Whether interrupts were enabled before the outermost guard was taken.
+static INTERRUPTS_WERE_ENABLED: AtomicBool = AtomicBool::new(false);
+
+///
+/// A spinlock that disables interrupts while held. <br>
+/// Locks shared with interrupt handlers must be of this type: an interrupt arriving while
+/// the lock is held would otherwise spin forever on the same core.
+///
+pub struct IrqSafeMutex<T: ?Sized> {
+    inner: spin::Mutex<T>,
+}
+
+///
+/// Guard of an `IrqSafeMutex`. <br>
+/// Releases the lock on drop. Dropping the last guard alive re-enables interrupts if they were
+/// enabled before the first was taken, so nested guards may be dropped in any order.
+///
+pub struct IrqSafeMutexGuard<'a, T: ?Sized + 'a> {
+    guard: ManuallyDrop<spin::MutexGuard<'a, T>>,
+}
+
+impl<T> IrqSafeMutex<T> {
+    pub const fn new(value: T) -> Self {
+        IrqSafeMutex {
+            inner: spin::Mutex::new(value),
+        }
+    }
+}
+
+impl<T: ?Sized> IrqSafeMutex<T> {
+    pub fn lock(&self) -> IrqSafeMutexGuard<T> {
+        disable_interrupts();
+
+        IrqSafeMutexGuard {
+            guard: ManuallyDrop::new(self.inner.lock()),
+        }
+    }
+
+    pub fn try_lock(&self) -> Option<IrqSafeMutexGuard<T>> {
+        disable_interrupts();
+
+        match self.inner.try_lock() {
+            Some(guard) => Some(IrqSafeMutexGuard {
+                guard: ManuallyDrop::new(guard),
+            }),
+            None => {
+                restore_interrupts();
+                None
+            }
+        }
+    }
+
+    /// Releases the lock regardless of its holder, leaving the interrupt flag untouched.
+    /// The holder's guard still counts as alive, so interrupts stay disabled for good.
+    ///
+    /// This function is unsafe as the holder may still use its guard afterwards. It is
+    /// only meant for the panic handler, see `panicking::prepare`.
+    pub unsafe fn force_unlock(&self) {
+        self.inner.force_unlock();
+    }
+}
+
+impl<T: ?Sized> Deref for IrqSafeMutexGuard<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.guard
+    }
+}
+
+impl<T: ?Sized> DerefMut for IrqSafeMutexGuard<'_, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        &mut self.guard
+    }
+}
+
+impl<T: ?Sized> Drop for IrqSafeMutexGuard<'_, T> {
+    fn drop(&mut self) {
+        // Unlock before enabling interrupts, so a handler can never find the lock taken.
+        unsafe { ManuallyDrop::drop(&mut self.guard) };
+        restore_interrupts();
+    }
+}
+
+/// Disables interrupts for a new guard, remembering their state if it is the outermost one.
+fn disable_interrupts() {
+    let enabled = interrupts::are_enabled();
+    interrupts::disable();
+
+    if HELD_GUARDS.fetch_add(1, Ordering::SeqCst) == 0 {
+        INTERRUPTS_WERE_ENABLED.store(enabled, Ordering::SeqCst);
+    }
+}
+
+/// Re-enables interrupts once the last guard is gone, if they were enabled before the first.
+fn restore_interrupts() {
+    if HELD_GUARDS.fetch_sub(1, Ordering::SeqCst) == 1
+        && INTERRUPTS_WERE_ENABLED.load(Ordering::SeqCst)
+    {
+        interrupts::enable();
+    }
+}
+
+#[test_case]
+fn test_irq_safe_mutex_restores_interrupts() {
+    let mutex = IrqSafeMutex::new(0);
+    assert!(interrupts::are_enabled());
+
+    {
+        let mut outer = mutex.lock();
+        *outer += 1;
+        assert!(!interrupts::are_enabled());
+
+        // Nested locks must not re-enable interrupts early.
+        let other = IrqSafeMutex::new(());
+        drop(other.lock());
+        assert!(!interrupts::are_enabled());
+        assert!(mutex.try_lock().is_none());
+    }
+
+    assert!(interrupts::are_enabled());
+    assert_eq!(*mutex.lock(), 1);
+}
+
+#[test_case]
+fn test_irq_safe_mutex_out_of_order_drop() {
+    let (outer, inner) = (IrqSafeMutex::new(()), IrqSafeMutex::new(()));
+
+    let outer_guard = outer.lock();
+    let inner_guard = inner.lock();
+    drop(outer_guard);
+    assert!(!interrupts::are_enabled()); // `inner` is still held
+    drop(inner_guard);
+    assert!(interrupts::are_enabled());
+}
@@ -1,9 +1,20 @@
 use super::{Task, TaskId};
-use alloc::{collections::BTreeMap, sync::Arc, task::Wake};
-use core::task::{Context, Poll, Waker};
+use crate::memory::slab::{SlabCache, SlabCacheStats};
+use alloc::{collections::BTreeMap, sync::Arc};
+use core::{
+    ptr::NonNull,
+    sync::atomic::{AtomicUsize, Ordering},
+    task::{Context, Poll, RawWaker, RawWakerVTable, Waker},
+};
 use crossbeam_queue::ArrayQueue;
 use x86_64::instructions::interrupts;
 
+/// Wakers of all tasks, allocated on a task's first poll and freed with its last `Waker`.
+static TASK_WAKERS: SlabCache<TaskWaker> = SlabCache::new("task wakers");
+
+const TASK_WAKER_VTABLE: RawWakerVTable =
+    RawWakerVTable::new(clone_waker, wake, wake_by_ref, drop_waker);
+
 pub struct Executor {
     tasks: BTreeMap<TaskId, Task>,
     task_queue: Arc<ArrayQueue<TaskId>>,
@@ -13,6 +24,7 @@ pub struct Executor {
 struct TaskWaker {
     task_id: TaskId,
     task_queue: Arc<ArrayQueue<TaskId>>,
+    references: AtomicUsize, // `Waker`s sharing this waker
 }
 
 impl Executor {
@@ -71,12 +83,27 @@ impl Executor {
     }
 }
 
+/// Returns a snapshot of the task waker cache's usage.
+pub fn waker_stats() -> SlabCacheStats {
+    TASK_WAKERS.stats()
+}
+
 impl TaskWaker {
     fn new(task_id: TaskId, task_queue: Arc<ArrayQueue<TaskId>>) -> Waker {
-        Waker::from(Arc::new(TaskWaker {
-            task_id,
-            task_queue,
-        }))
+        let waker = TASK_WAKERS
+            .alloc_with(TaskWaker {
+                task_id,
+                task_queue,
+                references: AtomicUsize::new(1),
+            })
+            .expect("Failed to allocate a task waker.");
+
+        unsafe {
+            Waker::from_raw(RawWaker::new(
+                waker.as_ptr() as *const (),
+                &TASK_WAKER_VTABLE,
+            ))
+        }
     }
 
     fn wake_task(&self) {
@@ -86,12 +113,27 @@ impl TaskWaker {
     }
 }
 
-impl Wake for TaskWaker {
-    fn wake(self: Arc<Self>) {
-        self.wake_task();
-    }
+// The `RawWaker` functions below receive a pointer to a `TaskWaker` from `TASK_WAKERS`,
+// which is freed once the last `Waker` referring to it is dropped.
+
+unsafe fn clone_waker(data: *const ()) -> RawWaker {
+    let waker = &*(data as *const TaskWaker);
+    waker.references.fetch_add(1, Ordering::Relaxed);
+    RawWaker::new(data, &TASK_WAKER_VTABLE)
+}
+
+unsafe fn wake(data: *const ()) {
+    wake_by_ref(data);
+    drop_waker(data);
+}
+
+unsafe fn wake_by_ref(data: *const ()) {
+    (*(data as *const TaskWaker)).wake_task();
+}
 
-    fn wake_by_ref(self: &Arc<Self>) {
-        self.wake_task();
+unsafe fn drop_waker(data: *const ()) {
+    let waker = data as *mut TaskWaker;
+    if (*waker).references.fetch_sub(1, Ordering::AcqRel) == 1 {
+        TASK_WAKERS.free(NonNull::new_unchecked(waker));
     }
 }
@@ -1,4 +1,9 @@
-use crate::{print, println};
+use super::executor;
+use crate::{
+    console_print, console_println, logging,
+    memory::{heap, paging},
+    output::vga,
+};
 use conquer_once::spin::OnceCell;
 use core::{
     pin::Pin,
@@ -6,7 +11,12 @@ use core::{
 };
 use crossbeam_queue::ArrayQueue;
 use futures_util::{task::AtomicWaker, Stream, StreamExt};
-use pc_keyboard::{layouts, DecodedKey, HandleControl, Keyboard, ScancodeSet1};
+use pc_keyboard::{
+    layouts, DecodedKey, HandleControl, KeyCode, KeyEvent, KeyState, Keyboard, ScancodeSet1,
+};
+
+/// Number of lines moved by a single Shift+PageUp/PageDown.
+const SCROLL_STEP: usize = 12;
 
 pub struct ScancodeStream {
     _private: (),
@@ -54,25 +64,105 @@ static WAKER: AtomicWaker = AtomicWaker::new();
 pub(crate) fn add_scancode(scancode: u8) {
     if let Ok(queue) = SCANCODE_QUEUE.try_get() {
         if let Err(_) = queue.push(scancode) {
-            println!("[WARN]: Scancode queue full -> Dropping keyboard input.");
+            log::warn!("Scancode queue full -> Dropping keyboard input.");
         } else {
             WAKER.wake();
         }
     } else {
-        println!("[WARN]: Scancode queue uninitialized.");
+        log::warn!("Scancode queue uninitialized.");
+    }
+}
+
+/// Modifier key state tracked alongside the decoder to recognise console shortcuts.
+#[derive(Debug, Default)]
+struct Modifiers {
+    left_shift: bool,
+    right_shift: bool,
+    left_alt: bool,
+    right_alt: bool,
+}
+
+impl Modifiers {
+    fn update(&mut self, key_event: &KeyEvent) {
+        let pressed = key_event.state == KeyState::Down;
+        match key_event.code {
+            KeyCode::ShiftLeft => self.left_shift = pressed,
+            KeyCode::ShiftRight => self.right_shift = pressed,
+            KeyCode::AltLeft => self.left_alt = pressed,
+            KeyCode::AltRight => self.right_alt = pressed,
+            _ => {}
+        }
+    }
+
+    fn shift(&self) -> bool {
+        self.left_shift || self.right_shift
+    }
+
+    fn alt(&self) -> bool {
+        self.left_alt || self.right_alt
+    }
+}
+
+///
+/// Handles console shortcuts, returning `true` if the key event was consumed. <br>
+/// Shift+PageUp/PageDown scroll, Alt+F1..F6 switch console, Alt+D prints the kernel log,
+/// Alt+M the heap and task waker usage and Alt+P the page table mappings, all to the active
+/// console.
+///
+fn handle_shortcut(key_event: &KeyEvent, modifiers: &Modifiers) -> bool {
+    if key_event.state != KeyState::Down {
+        return false;
+    }
+
+    if modifiers.shift() {
+        match key_event.code {
+            KeyCode::PageUp => vga::scroll_up(SCROLL_STEP),
+            KeyCode::PageDown => vga::scroll_down(SCROLL_STEP),
+            _ => return false,
+        }
+    } else if modifiers.alt() {
+        match key_event.code {
+            KeyCode::F1 => vga::switch_console(0),
+            KeyCode::F2 => vga::switch_console(1),
+            KeyCode::F3 => vga::switch_console(2),
+            KeyCode::F4 => vga::switch_console(3),
+            KeyCode::F5 => vga::switch_console(4),
+            KeyCode::F6 => vga::switch_console(5),
+            KeyCode::D => logging::dmesg(vga::active_console()),
+            KeyCode::M => console_println!(
+                vga::active_console(),
+                "\n{}\n{}",
+                heap::stats(),
+                executor::waker_stats()
+            ),
+            KeyCode::P => paging::dump(vga::active_console()),
+            _ => return false,
+        }
+    } else {
+        return false;
     }
+
+    true
 }
 
 pub async fn print_keypresses() {
     let mut scancodes = ScancodeStream::new();
     let mut keyboard = Keyboard::new(layouts::Us104Key, ScancodeSet1, HandleControl::Ignore);
+    let mut modifiers = Modifiers::default();
 
     while let Some(scancode) = scancodes.next().await {
         if let Ok(Some(key_event)) = keyboard.add_byte(scancode) {
+            modifiers.update(&key_event);
+            if handle_shortcut(&key_event, &modifiers) {
+                continue;
+            }
+
+            // Typed keys are echoed to the console on screen.
             if let Some(key) = keyboard.process_keyevent(key_event) {
+                let console = vga::active_console();
                 match key {
-                    DecodedKey::RawKey(key) => print!("{:?}", key),
-                    DecodedKey::Unicode(character) => print!("{}", character),
+                    DecodedKey::RawKey(key) => console_print!(console, "{:?}", key),
+                    DecodedKey::Unicode(character) => console_print!(console, "{}", character),
                 }
             }
         }
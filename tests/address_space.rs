@@ -0,0 +1,158 @@
+#![feature(custom_test_frameworks)]
+#![no_main]
+#![no_std]
+#![test_runner(nuclea_r_os::test_runner)]
+#![reexport_test_harness_main = "test_main"]
+
+use bootloader::{entry_point, BootInfo};
+use core::panic::PanicInfo;
+use nuclea_r_os::memory::{
+    address_space::{self, MapError, Permissions},
+    frame::{self, GlobalFrameAllocator},
+    heap, paging, vmalloc,
+};
+use x86_64::{structures::paging::PageTableFlags as PTFlags, VirtAddr};
+
+entry_point!(main);
+
+fn main(_boot_info: &'static BootInfo) -> ! {
+    nuclea_r_os::init();
+
+    let phys_mem_offset = VirtAddr::new(_boot_info.physical_memory_offset);
+    unsafe {
+        address_space::init(phys_mem_offset);
+        frame::init(&_boot_info.memory_map, phys_mem_offset);
+    }
+    address_space::with(|space| heap::init_heap(space.mapper(), &mut GlobalFrameAllocator))
+        .expect("Heap Initialization Failed.");
+
+    test_main();
+
+    nuclea_r_os::hlt_loop();
+}
+
+#[panic_handler]
+fn panic(_info: &PanicInfo) -> ! {
+    nuclea_r_os::test_panic_handler(_info);
+}
+
+fn free_frames() -> usize {
+    frame::with_allocator(|allocator| allocator.free_frames()).unwrap()
+}
+
+#[test_case]
+fn test_map_and_unmap_range() {
+    let start = vmalloc::reserve(4 * 4096).unwrap();
+    address_space::with(|space| space.map_range(start, 4096, Permissions::READ_WRITE)).unwrap();
+    unsafe { address_space::with(|space| space.unmap_range(start, 4096)) }.unwrap();
+
+    // Page tables for the range exist now, so only the mapped frames count.
+    let before = free_frames();
+    address_space::with(|space| space.map_range(start, 4 * 4096, Permissions::READ_WRITE)).unwrap();
+    assert_eq!(free_frames(), before - 4);
+    assert!(address_space::with(|space| space.translate(start + 3 * 4096_u64)).is_some());
+
+    unsafe { start.as_mut_ptr::<u64>().write_volatile(42) };
+    assert_eq!(unsafe { start.as_ptr::<u64>().read_volatile() }, 42);
+
+    unsafe { address_space::with(|space| space.unmap_range(start, 4 * 4096)) }.unwrap();
+    assert_eq!(free_frames(), before);
+    assert_eq!(address_space::with(|space| space.translate(start)), None);
+
+    unsafe { vmalloc::vfree(start) };
+}
+
+#[test_case]
+fn test_map_range_twice() {
+    let start = vmalloc::vmalloc(4096).unwrap();
+    let result = address_space::with(|space| space.map_range(start, 4096, Permissions::READ));
+    assert_eq!(result, Err(MapError::AlreadyMapped));
+
+    unsafe { vmalloc::vfree(start) };
+}
+
+#[test_case]
+fn test_protect() {
+    let start = vmalloc::vmalloc(2 * 4096).unwrap();
+    let permissions = |address| address_space::with(|space| space.permissions(address)).unwrap();
+    assert!(permissions(start).is_writable());
+
+    let result =
+        unsafe { address_space::with(|space| space.protect(start, 4096, Permissions::READ)) };
+    assert_eq!(result, Ok(()));
+    assert!(!permissions(start).is_writable());
+    assert!(permissions(start + 4096_u64).is_writable());
+
+    unsafe { vmalloc::vfree(start) };
+}
+
+#[test_case]
+fn test_huge_pages() {
+    const HUGE_PAGE_SIZE: u64 = 2 * 1024 * 1024;
+
+    let area = vmalloc::reserve(3 * HUGE_PAGE_SIZE as usize).unwrap();
+    let start = area.align_up(HUGE_PAGE_SIZE);
+    let page_size = |address| address_space::with(|space| space.page_size(address));
+
+    let before = free_frames();
+    address_space::with(|space| {
+        space.map_range(start, HUGE_PAGE_SIZE as usize, Permissions::READ_WRITE)
+    })
+    .unwrap();
+    assert_eq!(page_size(start), Some(HUGE_PAGE_SIZE));
+    let mapped = free_frames();
+    assert!(mapped <= before - 512);
+
+    unsafe { (start + 4096_u64).as_mut_ptr::<u64>().write_volatile(42) };
+
+    // Protecting part of the page splits it, taking one frame for the new page table.
+    let result =
+        unsafe { address_space::with(|space| space.protect(start, 4096, Permissions::READ)) };
+    assert_eq!(result, Ok(()));
+    assert_eq!(free_frames(), mapped - 1);
+    assert_eq!(page_size(start), Some(4096));
+    assert_eq!(page_size(start + 4096_u64), Some(4096));
+
+    let permissions = |address| address_space::with(|space| space.permissions(address)).unwrap();
+    assert!(!permissions(start).is_writable());
+    assert!(permissions(start + 4096_u64).is_writable());
+    assert_eq!(
+        unsafe { (start + 4096_u64).as_ptr::<u64>().read_volatile() },
+        42
+    );
+
+    unsafe { address_space::with(|space| space.unmap_range(start, HUGE_PAGE_SIZE as usize)) }
+        .unwrap();
+    assert_eq!(free_frames(), mapped - 1 + 512);
+    assert_eq!(page_size(start), None);
+
+    unsafe { vmalloc::vfree(area) };
+}
+
+#[test_case]
+fn test_inspection() {
+    let start = vmalloc::vmalloc(2 * 4096).unwrap();
+    unsafe { address_space::with(|space| space.protect(start, 4096, Permissions::READ)) }.unwrap();
+
+    let mut ranges = [None; 2];
+    paging::for_each_mapped_range(|range| {
+        if range.start == start {
+            ranges[0] = Some(*range);
+        } else if range.start == start + 4096_u64 {
+            ranges[1] = Some(*range);
+        }
+    });
+    let (read_only, writable) = (ranges[0].unwrap(), ranges[1].unwrap());
+    assert_eq!(read_only.size, 4096);
+    assert!(!read_only.flags.contains(PTFlags::WRITABLE));
+    assert!(writable.flags.contains(PTFlags::WRITABLE));
+    assert!(!writable.flags.contains(PTFlags::USER_ACCESSIBLE));
+
+    let path = paging::translation_path(start + 8_u64).unwrap();
+    let physical = address_space::with(|space| space.translate(start + 8_u64));
+    assert_eq!(path.physical, physical);
+    assert!(path.steps.iter().all(|step| step.is_some()));
+    assert_eq!(path.steps[3].unwrap().level, 1);
+
+    unsafe { vmalloc::vfree(start) };
+}
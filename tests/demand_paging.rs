@@ -0,0 +1,101 @@
+#![feature(custom_test_frameworks)]
+#![no_main]
+#![no_std]
+#![test_runner(nuclea_r_os::test_runner)]
+#![reexport_test_harness_main = "test_main"]
+
+use bootloader::{entry_point, BootInfo};
+use core::panic::PanicInfo;
+use nuclea_r_os::memory::{
+    address_space::{self, Permissions},
+    frame::{self, GlobalFrameAllocator},
+    heap,
+    lazy::{self, LazyError},
+    vmalloc,
+};
+use x86_64::VirtAddr;
+
+entry_point!(main);
+
+fn main(_boot_info: &'static BootInfo) -> ! {
+    nuclea_r_os::init();
+
+    let phys_mem_offset = VirtAddr::new(_boot_info.physical_memory_offset);
+    unsafe {
+        address_space::init(phys_mem_offset);
+        frame::init(&_boot_info.memory_map, phys_mem_offset);
+    }
+    address_space::with(|space| heap::init_heap(space.mapper(), &mut GlobalFrameAllocator))
+        .expect("Heap Initialization Failed.");
+
+    test_main();
+
+    nuclea_r_os::hlt_loop();
+}
+
+#[panic_handler]
+fn panic(_info: &PanicInfo) -> ! {
+    nuclea_r_os::test_panic_handler(_info);
+}
+
+fn free_frames() -> usize {
+    frame::with_allocator(|allocator| allocator.free_frames()).unwrap()
+}
+
+fn is_mapped(address: VirtAddr) -> bool {
+    address_space::with(|space| space.translate(address)).is_some()
+}
+
+#[test_case]
+fn test_lazy_area_backed_on_touch() {
+    let before = free_frames();
+    let start = vmalloc::vmalloc_lazy(64 * 1024 * 1024).unwrap();
+    assert_eq!(free_frames(), before);
+
+    let first = start + 5 * 1024 * 1024_u64;
+    assert!(!is_mapped(first));
+    unsafe { first.as_mut_ptr::<u64>().write_volatile(42) };
+    assert!(is_mapped(first));
+    assert_eq!(unsafe { first.as_ptr::<u64>().read_volatile() }, 42);
+
+    // Page tables for the area exist now, so only the new page's frame counts.
+    let touched = free_frames();
+    let second = first + 4096_u64;
+    assert_eq!(unsafe { second.as_ptr::<u64>().read_volatile() }, 0);
+    assert_eq!(free_frames(), touched - 1);
+
+    unsafe { vmalloc::vfree(start) };
+    assert_eq!(free_frames(), touched + 1);
+    assert!(!is_mapped(first));
+}
+
+#[test_case]
+fn test_lazy_pages_are_zeroed() {
+    // Freed frames are reused first, so the lazy page likely gets the dirty one.
+    let dirty = vmalloc::vmalloc(4096).unwrap();
+    unsafe { core::ptr::write_bytes(dirty.as_mut_ptr::<u8>(), 0xff, 4096) };
+    unsafe { vmalloc::vfree(dirty) };
+
+    let start = vmalloc::vmalloc_lazy(4096).unwrap();
+    let page = unsafe { core::slice::from_raw_parts(start.as_ptr::<u8>(), 4096) };
+    assert!(page.iter().all(|&byte| byte == 0));
+
+    unsafe { vmalloc::vfree(start) };
+}
+
+#[test_case]
+fn test_overlapping_regions_rejected() {
+    let start = vmalloc::reserve(4 * 4096).unwrap();
+    assert_eq!(
+        lazy::register(start, 2 * 4096, Permissions::READ_WRITE),
+        Ok(())
+    );
+    assert_eq!(
+        lazy::register(start + 4096_u64, 2 * 4096, Permissions::READ_WRITE),
+        Err(LazyError::Overlapping)
+    );
+    assert!(lazy::unregister(start + 4096_u64));
+    assert!(!lazy::unregister(start));
+
+    unsafe { vmalloc::vfree(start) };
+}
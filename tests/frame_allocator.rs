@@ -0,0 +1,169 @@
+#![feature(custom_test_frameworks)]
+#![no_main]
+#![no_std]
+#![test_runner(nuclea_r_os::test_runner)]
+#![reexport_test_harness_main = "test_main"]
+
+use bootloader::{entry_point, BootInfo};
+use core::panic::PanicInfo;
+use nuclea_r_os::memory::{
+    buddy::{Zone, MAX_ORDER},
+    frame::{self, GlobalFrameAllocator},
+};
+use x86_64::{
+    structures::paging::{FrameAllocator, FrameDeallocator, PhysFrame},
+    PhysAddr, VirtAddr,
+};
+
+entry_point!(main);
+
+fn main(_boot_info: &'static BootInfo) -> ! {
+    nuclea_r_os::init();
+
+    let phys_mem_offset = VirtAddr::new(_boot_info.physical_memory_offset);
+    unsafe { frame::init(&_boot_info.memory_map, phys_mem_offset) };
+
+    test_main();
+
+    nuclea_r_os::hlt_loop();
+}
+
+#[panic_handler]
+fn panic(_info: &PanicInfo) -> ! {
+    nuclea_r_os::test_panic_handler(_info);
+}
+
+fn free_frames() -> usize {
+    frame::with_allocator(|allocator| allocator.free_frames()).unwrap()
+}
+
+/// Counts the free blocks of every order in every zone.
+fn free_blocks() -> [[usize; MAX_ORDER + 1]; 3] {
+    frame::with_allocator(|allocator| {
+        let mut counts = [[0; MAX_ORDER + 1]; 3];
+        for (zone, row) in [Zone::Dma, Zone::Dma32, Zone::Normal]
+            .iter()
+            .zip(&mut counts)
+        {
+            for (order, count) in row.iter_mut().enumerate() {
+                *count = allocator.free_blocks(*zone, order);
+            }
+        }
+        counts
+    })
+    .unwrap()
+}
+
+#[test_case]
+fn test_counters() {
+    let (total, used, free) = frame::with_allocator(|allocator| {
+        (
+            allocator.total_frames(),
+            allocator.used_frames(),
+            allocator.free_frames(),
+        )
+    })
+    .unwrap();
+
+    assert!(total > 0);
+    assert_eq!(used + free, total);
+}
+
+#[test_case]
+fn test_allocate_unique_frames() {
+    let mut allocator = GlobalFrameAllocator;
+    let free = free_frames();
+
+    let mut frames = [None; 64];
+    for slot in frames.iter_mut() {
+        *slot = allocator.allocate_frame();
+    }
+    assert_eq!(free_frames(), free - frames.len());
+
+    for (i, a) in frames.iter().enumerate() {
+        assert!(a.is_some());
+        assert!(frames[i + 1..].iter().all(|b| b != a));
+    }
+
+    for frame in frames.iter().flatten() {
+        unsafe { allocator.deallocate_frame(*frame) };
+    }
+    assert_eq!(free_frames(), free);
+}
+
+#[test_case]
+fn test_deallocated_frame_is_reused() {
+    let mut allocator = GlobalFrameAllocator;
+
+    let frame = allocator.allocate_frame().unwrap();
+    unsafe { allocator.deallocate_frame(frame) };
+
+    assert_eq!(allocator.allocate_frame(), Some(frame));
+    unsafe { allocator.deallocate_frame(frame) };
+}
+
+#[test_case]
+fn test_reserved_frames_are_not_allocated() {
+    let mut allocator = GlobalFrameAllocator;
+
+    // Reserve whatever would be handed out next.
+    let frame = allocator.allocate_frame().unwrap();
+    unsafe { allocator.deallocate_frame(frame) };
+
+    let free = frame::with_allocator(|allocator| {
+        let start = frame.start_address();
+        allocator.reserve(start..start + 4096u64);
+        allocator.free_frames()
+    })
+    .unwrap();
+
+    let next = allocator.allocate_frame().unwrap();
+    assert_ne!(next, frame);
+    assert!(frame::with_allocator(|allocator| allocator.is_used(frame)).unwrap());
+    assert_eq!(free_frames(), free - 1);
+    unsafe { allocator.deallocate_frame(next) };
+
+    // Frames outside usable memory count as used.
+    let vga = PhysFrame::containing_address(PhysAddr::new(0xb8000));
+    assert!(frame::with_allocator(|allocator| allocator.is_used(vga)).unwrap());
+}
+
+#[test_case]
+fn test_contiguous_blocks_are_aligned() {
+    for &order in &[1, 4, 9] {
+        let frame = frame::allocate_contiguous(order, Zone::Normal).unwrap();
+        assert_eq!(frame.start_address().as_u64() % (4096 << order), 0);
+
+        unsafe { frame::deallocate_contiguous(frame, order) };
+    }
+}
+
+#[test_case]
+fn test_zone_constraints() {
+    let dma = frame::allocate_contiguous(4, Zone::Dma).unwrap();
+    assert!(dma.start_address().as_u64() + (4096 << 4) <= 16 << 20);
+
+    let dma32 = frame::allocate_contiguous(4, Zone::Dma32).unwrap();
+    assert!(dma32.start_address().as_u64() + (4096 << 4) <= 4 << 30);
+
+    unsafe {
+        frame::deallocate_contiguous(dma, 4);
+        frame::deallocate_contiguous(dma32, 4);
+    }
+}
+
+#[test_case]
+fn test_freed_blocks_coalesce() {
+    let before = free_blocks();
+
+    // Splits a larger block, unless a single frame happens to be free already.
+    let first = frame::allocate_contiguous(0, Zone::Normal).unwrap();
+    let second = frame::allocate_contiguous(0, Zone::Normal).unwrap();
+    assert_ne!(free_blocks(), before);
+
+    unsafe {
+        frame::deallocate_contiguous(first, 0);
+        frame::deallocate_contiguous(second, 0);
+    }
+    assert_eq!(free_blocks(), before);
+}
@@ -0,0 +1,82 @@
+#![feature(custom_test_frameworks)]
+#![no_main]
+#![no_std]
+#![test_runner(nuclea_r_os::test_runner)]
+#![reexport_test_harness_main = "test_main"]
+
+use bootloader::{entry_point, BootInfo};
+use core::panic::PanicInfo;
+use nuclea_r_os::{
+    memory::{address_space, frame},
+    output::{framebuffer, vga::Color},
+    print,
+};
+use x86_64::VirtAddr;
+
+entry_point!(main);
+
+fn main(_boot_info: &'static BootInfo) -> ! {
+    nuclea_r_os::init();
+
+    let phys_mem_offset = VirtAddr::new(_boot_info.physical_memory_offset);
+    unsafe {
+        address_space::init(phys_mem_offset);
+        frame::init(&_boot_info.memory_map, phys_mem_offset);
+    }
+    framebuffer::init(640, 480).expect("Framebuffer Initialization Failed.");
+
+    test_main();
+
+    nuclea_r_os::hlt_loop();
+}
+
+#[panic_handler]
+fn panic(_info: &PanicInfo) -> ! {
+    nuclea_r_os::test_panic_handler(_info);
+}
+
+/// Counts the pixels of the character cell at (`col`, `row`) drawn in `rgb`.
+fn count_cell_pixels(col: usize, row: usize, rgb: u32) -> usize {
+    framebuffer::with_writer(|writer| {
+        let (width, height) = (writer.font().width, writer.font().height);
+        let mut count = 0;
+
+        for y in row * height..(row + 1) * height {
+            for x in col * width..(col + 1) * width {
+                if writer.pixel(x, y) == rgb {
+                    count += 1;
+                }
+            }
+        }
+        count
+    })
+    .expect("Framebuffer not initialized.")
+}
+
+#[test_case]
+fn test_framebuffer_mode() {
+    let info = framebuffer::with_writer(|writer| writer.info()).unwrap();
+    assert_eq!((info.width, info.height), (640, 480));
+    assert!(info.pitch >= info.width * info.bytes_per_pixel);
+}
+
+#[test_case]
+fn test_framebuffer_renders_glyphs() {
+    print!("\n# ");
+    let row = framebuffer::with_writer(|writer| writer.cursor_position.1).unwrap();
+
+    assert!(count_cell_pixels(0, row, Color::White.rgb()) > 0);
+    assert_eq!(count_cell_pixels(1, row, Color::White.rgb()), 0);
+}
+
+#[test_case]
+fn test_framebuffer_colors() {
+    framebuffer::with_writer(|writer| {
+        writer.color_code.foreground_color = Color::LightGreen;
+        writer.write_string("\n#");
+        writer.color_code.foreground_color = Color::White;
+    });
+    let row = framebuffer::with_writer(|writer| writer.cursor_position.1).unwrap();
+
+    assert!(count_cell_pixels(0, row, Color::LightGreen.rgb()) > 0);
+}
@@ -0,0 +1,53 @@
+#![no_main]
+#![no_std]
+
+use bootloader::{entry_point, BootInfo};
+use core::panic::PanicInfo;
+use nuclea_r_os::{
+    memory::frame::{self, GlobalFrameAllocator},
+    panicking,
+    qemu::{exit_qemu, QEMUExitCode},
+    serial_print, serial_println,
+};
+use x86_64::{
+    structures::paging::{FrameDeallocator, PhysFrame},
+    PhysAddr, VirtAddr,
+};
+
+entry_point!(main);
+
+fn main(_boot_info: &'static BootInfo) -> ! {
+    nuclea_r_os::init();
+
+    let phys_mem_offset = VirtAddr::new(_boot_info.physical_memory_offset);
+    unsafe { frame::init(&_boot_info.memory_map, phys_mem_offset) };
+
+    test_free_reserved_frame();
+
+    serial_println!("[FAILED] - Free of a reserved frame went undetected.\n");
+    exit_qemu(QEMUExitCode::Failed);
+
+    nuclea_r_os::hlt_loop();
+}
+
+#[panic_handler]
+fn panic(_info: &PanicInfo) -> ! {
+    if !panicking::message_contains(_info, "reserved or unusable frames") {
+        nuclea_r_os::test_panic_handler(_info);
+    }
+
+    serial_println!("[OK]");
+
+    serial_println!("Test complete! Exiting.\n");
+    exit_qemu(QEMUExitCode::Success);
+
+    nuclea_r_os::hlt_loop();
+}
+
+fn test_free_reserved_frame() {
+    serial_print!("\nfree_reserved_frame::test_free_reserved_frame... ");
+
+    // VGA text memory, never usable RAM.
+    let vga = PhysFrame::containing_address(PhysAddr::new(0xb8000));
+    unsafe { GlobalFrameAllocator.deallocate_frame(vga) };
+}
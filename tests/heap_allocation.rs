@@ -10,9 +10,10 @@ use alloc::{boxed::Box, vec::Vec};
 use bootloader::{entry_point, BootInfo};
 use core::panic::PanicInfo;
 use nuclea_r_os::memory::{
-    self,
-    heap::{self, HEAP_SIZE},
-    paging::BootInfoFrameAllocator,
+    address_space,
+    frame::{self, GlobalFrameAllocator},
+    heap::{self, tracker, HEAP_SIZE},
+    slab::SlabCache,
 };
 use x86_64::VirtAddr;
 
@@ -22,9 +23,12 @@ fn main(_boot_info: &'static BootInfo) -> ! {
     nuclea_r_os::init();
 
     let phys_mem_offset = VirtAddr::new(_boot_info.physical_memory_offset);
-    let mut mapper = unsafe { memory::paging::init_offset_page_table(phys_mem_offset) };
-    let mut frame_allocator = unsafe { BootInfoFrameAllocator::new(&_boot_info.memory_map) };
-    heap::init_heap(&mut mapper, &mut frame_allocator).expect("Heap Initialization Failed.");
+    unsafe {
+        address_space::init(phys_mem_offset);
+        frame::init(&_boot_info.memory_map, phys_mem_offset);
+    }
+    address_space::with(|space| heap::init_heap(space.mapper(), &mut GlobalFrameAllocator))
+        .expect("Heap Initialization Failed.");
 
     test_main();
 
@@ -74,3 +78,112 @@ fn test_memory_freeing_long_lived() {
     }
     assert_eq!(*long_lived, 1);
 }
+
+#[test_case]
+fn test_heap_growth() {
+    let size = 4 * HEAP_SIZE;
+    let vec = alloc::vec![0xa5_u8; size];
+    assert!(heap::size() >= size);
+    assert!(vec.iter().all(|&b| b == 0xa5));
+}
+
+#[test_case]
+fn test_heap_limit() {
+    let previous = heap::limit();
+    heap::set_limit(heap::size());
+
+    let mut vec: Vec<u8> = Vec::new();
+    assert!(vec.try_reserve_exact(heap::limit() + 1).is_err());
+
+    heap::set_limit(previous);
+    assert!(vec.try_reserve_exact(2 * HEAP_SIZE).is_ok());
+}
+
+#[test_case]
+fn test_memory_returned() {
+    let before = heap::stats();
+
+    let boxed = Box::new(17_u64);
+    let vec: Vec<u8> = Vec::with_capacity(4096);
+    let during = heap::stats();
+    assert!(during.allocated >= before.allocated + 4096 + 8);
+    assert_eq!(during.allocations, before.allocations + 2);
+
+    drop(boxed);
+    drop(vec);
+    let after = heap::stats();
+    assert_eq!(after.allocated, before.allocated);
+    assert_eq!(after.deallocations, before.deallocations + 2);
+    assert!(after.high_water_mark >= during.allocated);
+    assert_eq!(after.free, after.size - after.allocated);
+}
+
+#[test_case]
+fn test_slabs_returned() {
+    heap::shrink();
+    let before = heap::stats();
+
+    let boxes: Vec<Box<u64>> = (0..10_000).map(Box::new).collect();
+    assert!(heap::stats().slabs.iter().sum::<usize>() > before.slabs.iter().sum());
+    drop(boxes);
+
+    assert!(heap::shrink() > 0);
+    let after = heap::stats();
+    assert_eq!(after.slabs, before.slabs);
+    assert_eq!(after.fallback_used, before.fallback_used);
+}
+
+#[test_case]
+fn test_slab_cache() {
+    static CACHE: SlabCache<[u64; 4]> = SlabCache::with_constructor("test", || [7; 4]);
+
+    let objects: Vec<_> = (0..100).map(|_| CACHE.alloc().unwrap()).collect();
+    assert!(objects
+        .iter()
+        .all(|object| unsafe { *object.as_ref() } == [7; 4]));
+
+    let stats = CACHE.stats();
+    assert_eq!(stats.name, "test");
+    assert_eq!(stats.in_use, 100);
+    assert!(stats.slabs >= 100 * stats.object_size / stats.slab_size);
+
+    for object in objects {
+        unsafe { CACHE.free(object) };
+    }
+    let stats = CACHE.stats();
+    assert_eq!(
+        (stats.in_use, stats.frees, stats.high_water_mark),
+        (0, 100, 100)
+    );
+    assert_eq!(stats.slabs, 1); // One empty slab is kept
+    assert_eq!(CACHE.shrink(), stats.slab_size);
+    assert_eq!(CACHE.stats().slabs, 0);
+}
+
+#[test_case]
+fn test_slab_cache_drop() {
+    let allocated = heap::stats().allocated;
+    {
+        let cache: SlabCache<u64> = SlabCache::new("local");
+        let object = cache.alloc_with(1).unwrap();
+        unsafe { cache.free(object) };
+        assert_eq!(cache.stats().slabs, 1);
+    }
+    assert_eq!(heap::stats().allocated, allocated); // The kept slab is returned
+}
+
+#[test_case]
+fn test_leak_tracker() {
+    let snapshot = tracker::snapshot();
+
+    drop(Box::new(1_u64));
+    let leaked = Box::leak(Box::new(2_u64));
+    assert_eq!(snapshot.leak_count(), 1);
+    snapshot.for_each_leak(|allocation| {
+        assert_eq!(allocation.address, leaked as *mut u64 as usize);
+        assert_eq!(allocation.layout.size(), 8);
+    });
+
+    unsafe { drop(Box::from_raw(leaked)) };
+    snapshot.assert_no_leaks();
+}
@@ -0,0 +1,65 @@
+#![no_main]
+#![no_std]
+
+extern crate alloc;
+
+use alloc::alloc::{alloc, dealloc, Layout};
+use bootloader::{entry_point, BootInfo};
+use core::panic::PanicInfo;
+use nuclea_r_os::{
+    memory::{
+        address_space,
+        frame::{self, GlobalFrameAllocator},
+        heap,
+    },
+    panicking,
+    qemu::{exit_qemu, QEMUExitCode},
+    serial_print, serial_println,
+};
+use x86_64::VirtAddr;
+
+entry_point!(main);
+
+fn main(_boot_info: &'static BootInfo) -> ! {
+    nuclea_r_os::init();
+
+    let phys_mem_offset = VirtAddr::new(_boot_info.physical_memory_offset);
+    unsafe {
+        address_space::init(phys_mem_offset);
+        frame::init(&_boot_info.memory_map, phys_mem_offset);
+    }
+    address_space::with(|space| heap::init_heap(space.mapper(), &mut GlobalFrameAllocator))
+        .expect("Heap Initialization Failed.");
+
+    test_overflow();
+
+    serial_println!("[FAILED] - Buffer overflow went undetected.\n");
+    exit_qemu(QEMUExitCode::Failed);
+
+    nuclea_r_os::hlt_loop();
+}
+
+#[panic_handler]
+fn panic(_info: &PanicInfo) -> ! {
+    if !panicking::message_contains(_info, "Heap corruption: Buffer overflow") {
+        nuclea_r_os::test_panic_handler(_info);
+    }
+
+    serial_println!("[OK]");
+
+    serial_println!("Test complete! Exiting.\n");
+    exit_qemu(QEMUExitCode::Success);
+
+    nuclea_r_os::hlt_loop();
+}
+
+fn test_overflow() {
+    serial_print!("\nheap_debug_overflow::test_overflow... ");
+
+    let layout = Layout::new::<u64>();
+    unsafe {
+        let ptr = alloc(layout);
+        ptr.add(layout.size()).write_volatile(0);
+        dealloc(ptr, layout);
+    }
+}
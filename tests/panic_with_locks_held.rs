@@ -0,0 +1,34 @@
+#![no_main]
+#![no_std]
+
+use core::panic::PanicInfo;
+use nuclea_r_os::{
+    output::{serial::SERIAL1, vga::WRITER},
+    panicking, println,
+    qemu::{exit_qemu, QEMUExitCode},
+    serial_print, serial_println,
+};
+
+#[no_mangle]
+pub extern "C" fn _start() -> ! {
+    serial_print!("\npanic_with_locks_held::test_panic_with_locks_held... ");
+
+    // Panic while holding both output locks, as if inside `_print`.
+    let _writer = WRITER.lock();
+    let _serial = SERIAL1.lock();
+    panic!("Panicking with the console locks held.");
+}
+
+#[panic_handler]
+fn panic(_info: &PanicInfo) -> ! {
+    panicking::prepare(_info);
+
+    // Would spin forever if `prepare` had not released the locks.
+    println!("Panic output reached the console.");
+    serial_println!("[OK]");
+
+    serial_println!("Test complete! Exiting.\n");
+    exit_qemu(QEMUExitCode::Success);
+
+    nuclea_r_os::hlt_loop();
+}
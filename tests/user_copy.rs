@@ -0,0 +1,89 @@
+#![feature(custom_test_frameworks)]
+#![no_main]
+#![no_std]
+#![test_runner(nuclea_r_os::test_runner)]
+#![reexport_test_harness_main = "test_main"]
+
+use bootloader::{entry_point, BootInfo};
+use core::panic::PanicInfo;
+use nuclea_r_os::memory::{
+    address_space::{self, Permissions},
+    frame,
+    user::{self, UserCopyError},
+};
+use x86_64::VirtAddr;
+
+const USER_PAGE: u64 = 0x0000_6000_0000_0000; // Unused by the kernel
+
+entry_point!(main);
+
+fn main(_boot_info: &'static BootInfo) -> ! {
+    nuclea_r_os::init();
+
+    let phys_mem_offset = VirtAddr::new(_boot_info.physical_memory_offset);
+    unsafe {
+        address_space::init(phys_mem_offset);
+        frame::init(&_boot_info.memory_map, phys_mem_offset);
+    }
+
+    test_main();
+
+    nuclea_r_os::hlt_loop();
+}
+
+#[panic_handler]
+fn panic(_info: &PanicInfo) -> ! {
+    nuclea_r_os::test_panic_handler(_info);
+}
+
+/// Runs `f` with a page of user memory mapped with `permissions`.
+fn with_user_page(permissions: Permissions, f: impl FnOnce(VirtAddr)) {
+    let page = VirtAddr::new(USER_PAGE);
+    address_space::with(|space| space.map_range(page, 4096, permissions.user())).unwrap();
+    f(page);
+    unsafe { address_space::with(|space| space.unmap_range(page, 4096)) }.unwrap();
+}
+
+#[test_case]
+fn test_copy_round_trip() {
+    with_user_page(Permissions::READ_WRITE, |page| {
+        let address = page + 4090_u64; // Crossing into the next, unmapped page would fault
+        assert_eq!(user::copy_to_user(address, b"hello!"), Ok(()));
+
+        let mut buffer = [0; 6];
+        assert_eq!(user::copy_from_user(&mut buffer, address), Ok(()));
+        assert_eq!(&buffer, b"hello!");
+    });
+}
+
+#[test_case]
+fn test_copy_rejects_kernel_memory() {
+    let kernel = [1_u8; 8];
+    let mut buffer = [0; 8];
+
+    let result = user::copy_from_user(&mut buffer, VirtAddr::from_ptr(&kernel));
+    assert_eq!(result, Err(UserCopyError::BadAddress));
+    let result = user::copy_to_user(VirtAddr::from_ptr(&kernel), &buffer);
+    assert_eq!(result, Err(UserCopyError::BadAddress));
+    assert_eq!(kernel, [1; 8]);
+}
+
+#[test_case]
+fn test_copy_rejects_read_only_memory() {
+    with_user_page(Permissions::READ, |page| {
+        assert_eq!(
+            user::copy_to_user(page, b"hello!"),
+            Err(UserCopyError::BadAddress)
+        );
+    });
+}
+
+#[test_case]
+fn test_copy_fault_is_reported() {
+    with_user_page(Permissions::READ_WRITE, |page| {
+        let mut buffer = [0; 16];
+        let result = user::copy_from_user(&mut buffer, page + 4088_u64);
+        assert_eq!(result, Err(UserCopyError::Fault));
+        assert_eq!(buffer[8..], [0; 8]);
+    });
+}
@@ -0,0 +1,115 @@
+#![feature(custom_test_frameworks)]
+#![no_main]
+#![no_std]
+#![test_runner(nuclea_r_os::test_runner)]
+#![reexport_test_harness_main = "test_main"]
+
+use bootloader::{entry_point, BootInfo};
+use core::panic::PanicInfo;
+use nuclea_r_os::{
+    output::{
+        graphics::{self, Mode},
+        vga::Color,
+    },
+    println,
+};
+use x86_64::VirtAddr;
+
+entry_point!(main);
+
+fn main(_boot_info: &'static BootInfo) -> ! {
+    nuclea_r_os::init();
+    graphics::init(VirtAddr::new(_boot_info.physical_memory_offset));
+
+    test_main();
+
+    nuclea_r_os::hlt_loop();
+}
+
+#[panic_handler]
+fn panic(_info: &PanicInfo) -> ! {
+    nuclea_r_os::test_panic_handler(_info);
+}
+
+/// Reads the character currently displayed at (`col`, `row`) of the text buffer.
+fn displayed_char(col: usize, row: usize) -> u8 {
+    let vga = 0xb8000 as *const u16;
+    (unsafe { vga.add(row * 80 + col).read_volatile() } & 0xff) as u8
+}
+
+#[test_case]
+fn test_mode_13h_primitives() {
+    graphics::set_mode(Mode::Mode13h).expect("Failed to set mode 13h.");
+
+    graphics::with_screen(|screen| {
+        assert_eq!((screen.width(), screen.height()), (320, 200));
+        assert_eq!(screen.pixel(0, 0), Color::Black as u8);
+
+        screen.set_pixel(10, 10, 200);
+        assert_eq!(screen.pixel(10, 10), 200);
+
+        screen.draw_line((0, 0), (50, 50), Color::Red);
+        assert_eq!(screen.pixel(25, 25), Color::Red as u8);
+
+        screen.fill_rect(100, 100, 10, 10, Color::Green);
+        assert_eq!(screen.pixel(109, 109), Color::Green as u8);
+        assert_eq!(screen.pixel(110, 110), Color::Black as u8);
+
+        screen.blit(200, 20, 2, &[1, 2, 3, 4]);
+        assert_eq!(screen.pixel(201, 21), 4);
+
+        // Drawing off-screen is clipped rather than wrapping around.
+        screen.fill_rect(318, 198, 10, 10, Color::White);
+        assert_eq!(screen.pixel(0, 199), Color::Black as u8);
+    })
+    .expect("Graphics mode not set.");
+}
+
+#[test_case]
+fn test_mode_12h_primitives() {
+    graphics::set_mode(Mode::Mode12h).expect("Failed to set mode 12h.");
+
+    graphics::with_screen(|screen| {
+        assert_eq!((screen.width(), screen.height()), (640, 480));
+
+        // Neighbouring pixels share a byte in every plane.
+        screen.set_pixel(8, 0, Color::Yellow);
+        screen.set_pixel(9, 0, Color::Blue);
+        assert_eq!(screen.pixel(8, 0), Color::Yellow as u8);
+        assert_eq!(screen.pixel(9, 0), Color::Blue as u8);
+        assert_eq!(screen.pixel(10, 0), Color::Black as u8);
+
+        screen.draw_rect(20, 20, 30, 10, Color::LightCyan);
+        assert_eq!(screen.pixel(49, 29), Color::LightCyan as u8);
+        assert_eq!(screen.pixel(30, 25), Color::Black as u8);
+
+        screen.draw_text(100, 100, "#", Color::White, Color::Black);
+        let lit = (100..116)
+            .flat_map(|y| (100..108).map(move |x| (x, y)))
+            .filter(|&(x, y)| screen.pixel(x, y) == Color::White as u8)
+            .count();
+        assert!(lit > 0);
+    })
+    .expect("Graphics mode not set.");
+}
+
+#[test_case]
+fn test_palette() {
+    graphics::set_palette(100, 0xfc_8040);
+    assert_eq!(graphics::palette(100), 0xfc_8040);
+
+    graphics::load_default_palette();
+    // The DAC drops the low 2 bits of each component.
+    assert_eq!(graphics::palette(4), Color::Red.rgb() & 0xfc_fcfc);
+}
+
+#[test_case]
+fn test_return_to_text_mode() {
+    graphics::set_mode(Mode::Text).expect("Failed to return to text mode.");
+    assert_eq!(graphics::mode(), Mode::Text);
+    assert!(graphics::with_screen(|_| ()).is_none());
+
+    println!("\nX");
+    let row = nuclea_r_os::output::vga::WRITER.lock().cursor_position.1 - 1;
+    assert_eq!(displayed_char(0, row), b'X');
+}
@@ -0,0 +1,78 @@
+#![feature(custom_test_frameworks)]
+#![no_main]
+#![no_std]
+#![test_runner(nuclea_r_os::test_runner)]
+#![reexport_test_harness_main = "test_main"]
+
+use bootloader::{entry_point, BootInfo};
+use core::{fmt::Write, panic::PanicInfo};
+use nuclea_r_os::memory::{self, heap, paging::BootInfoFrameAllocator};
+use nuclea_r_os::output::vga::WRITER;
+use x86_64::{instructions::interrupts, VirtAddr};
+
+entry_point!(main);
+
+fn main(_boot_info: &'static BootInfo) -> ! {
+    nuclea_r_os::init();
+
+    let phys_mem_offset = VirtAddr::new(_boot_info.physical_memory_offset);
+    let mut mapper = unsafe { memory::paging::init_offset_page_table(phys_mem_offset) };
+    let mut frame_allocator = unsafe { BootInfoFrameAllocator::new(&_boot_info.memory_map) };
+    heap::init_heap(&mut mapper, &mut frame_allocator).expect("Heap Initialization Failed.");
+
+    test_main();
+
+    nuclea_r_os::hlt_loop();
+}
+
+#[panic_handler]
+fn panic(_info: &PanicInfo) -> ! {
+    nuclea_r_os::test_panic_handler(_info);
+}
+
+/// Reads the character currently displayed at the start of `row`.
+fn displayed_char(row: usize) -> u8 {
+    let vga = 0xb8000 as *const u16;
+    (unsafe { vga.add(row * 80).read_volatile() } & 0xff) as u8
+}
+
+#[test_case]
+fn test_scrollback_restores_lines() {
+    interrupts::without_interrupts(|| {
+        let mut writer = WRITER.lock();
+        writer.enable_scrollback(50);
+
+        // Fill the screen with lines starting 'A'..='Z', pushing the first ones into history.
+        for letter in b'A'..=b'Z' {
+            writeln!(writer, "{}", letter as char).expect("Failed to write to line.");
+        }
+
+        let bottom = writer.cursor_position.1;
+        assert_eq!(displayed_char(bottom - 1), b'Z');
+
+        writer.scroll_up(1);
+        assert_eq!(displayed_char(bottom - 1), b'Y');
+        assert_eq!(displayed_char(bottom), b'Z');
+
+        writer.scroll_down(1);
+        assert_eq!(displayed_char(bottom - 1), b'Z');
+    });
+}
+
+#[test_case]
+fn test_scrollback_snaps_back_on_output() {
+    interrupts::without_interrupts(|| {
+        let mut writer = WRITER.lock();
+        writer.enable_scrollback(50);
+
+        for _ in 0..30 {
+            writeln!(writer, "-").expect("Failed to write to line.");
+        }
+
+        writer.scroll_up(10);
+        writeln!(writer, "live").expect("Failed to write to line.");
+
+        let bottom = writer.cursor_position.1;
+        assert_eq!(displayed_char(bottom - 1), b'l');
+    });
+}
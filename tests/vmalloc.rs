@@ -0,0 +1,107 @@
+#![feature(custom_test_frameworks)]
+#![no_main]
+#![no_std]
+#![test_runner(nuclea_r_os::test_runner)]
+#![reexport_test_harness_main = "test_main"]
+
+use bootloader::{entry_point, BootInfo};
+use core::panic::PanicInfo;
+use nuclea_r_os::memory::{
+    address_space,
+    frame::{self, GlobalFrameAllocator},
+    heap::{self, HEAP_MAX_SIZE},
+    vmalloc::{self, VMALLOC_SIZE, VMALLOC_START},
+};
+use x86_64::{PhysAddr, VirtAddr};
+
+entry_point!(main);
+
+fn main(_boot_info: &'static BootInfo) -> ! {
+    nuclea_r_os::init();
+
+    let phys_mem_offset = VirtAddr::new(_boot_info.physical_memory_offset);
+    unsafe {
+        address_space::init(phys_mem_offset);
+        frame::init(&_boot_info.memory_map, phys_mem_offset);
+    }
+    address_space::with(|space| heap::init_heap(space.mapper(), &mut GlobalFrameAllocator))
+        .expect("Heap Initialization Failed.");
+
+    test_main();
+
+    nuclea_r_os::hlt_loop();
+}
+
+#[panic_handler]
+fn panic(_info: &PanicInfo) -> ! {
+    nuclea_r_os::test_panic_handler(_info);
+}
+
+fn free_frames() -> usize {
+    frame::with_allocator(|allocator| allocator.free_frames()).unwrap()
+}
+
+fn is_mapped(address: VirtAddr) -> bool {
+    address_space::with(|space| space.translate(address)).is_some()
+}
+
+#[test_case]
+fn test_vmalloc() {
+    let size = 5 * 4096;
+    let start = vmalloc::vmalloc(size).unwrap();
+    assert!((VMALLOC_START..VMALLOC_START + VMALLOC_SIZE).contains(&start.as_u64()));
+
+    let memory = unsafe { core::slice::from_raw_parts_mut(start.as_mut_ptr::<u8>(), size) };
+    memory.fill(0x42);
+    assert!(memory.iter().all(|&b| b == 0x42));
+
+    unsafe { vmalloc::vfree(start) };
+    assert!(!is_mapped(start));
+}
+
+#[test_case]
+fn test_vfree_returns_frames() {
+    // Warm up, so that page tables for the range already exist.
+    unsafe { vmalloc::vfree(vmalloc::vmalloc(4096).unwrap()) };
+
+    let (before, used) = (free_frames(), vmalloc::used());
+    let start = vmalloc::vmalloc(8 * 4096).unwrap();
+    assert_eq!(free_frames(), before - 8);
+
+    unsafe { vmalloc::vfree(start) };
+    assert_eq!(free_frames(), before);
+    assert_eq!(vmalloc::used(), used);
+}
+
+#[test_case]
+fn test_heap_range_reserved() {
+    // The whole range the heap may grow into, not only what is mapped.
+    assert!(vmalloc::used() >= HEAP_MAX_SIZE as u64);
+}
+
+#[test_case]
+fn test_guard_pages() {
+    let first = vmalloc::vmalloc(4096).unwrap();
+    let second = vmalloc::vmalloc(4096).unwrap();
+    assert!(second.as_u64() >= first.as_u64() + 2 * 4096);
+    assert!(!is_mapped(first + 4096_u64));
+
+    unsafe {
+        vmalloc::vfree(first);
+        vmalloc::vfree(second);
+    }
+}
+
+#[test_case]
+fn test_map_mmio() {
+    // The VGA text buffer, at an offset within its frame.
+    let address = vmalloc::map_mmio(PhysAddr::new(0xb8010), 16).unwrap();
+    assert_eq!(address.as_u64() % 4096, 0x10);
+    let translated = address_space::with(|space| space.translate(address));
+    assert_eq!(translated, Some(PhysAddr::new(0xb8010)));
+
+    let before = free_frames();
+    unsafe { vmalloc::vfree(address) };
+    assert_eq!(free_frames(), before);
+    assert!(!is_mapped(address));
+}
@@ -0,0 +1,76 @@
+#![feature(abi_x86_interrupt)]
+#![no_main]
+#![no_std]
+
+use bootloader::{entry_point, BootInfo};
+use core::panic::PanicInfo;
+use lazy_static::lazy_static;
+use nuclea_r_os::{
+    memory::{address_space, frame, protection},
+    qemu::{exit_qemu, QEMUExitCode},
+    serial_print, serial_println,
+};
+use x86_64::{
+    registers::control::Cr2,
+    structures::idt::{InterruptDescriptorTable, InterruptStackFrame, PageFaultErrorCode},
+    VirtAddr,
+};
+
+lazy_static! {
+    static ref TEST_IDT: InterruptDescriptorTable = {
+        let mut idt = InterruptDescriptorTable::new();
+        idt.page_fault.set_handler_fn(test_page_fault_handler);
+        idt
+    };
+}
+
+entry_point!(main);
+
+fn main(_boot_info: &'static BootInfo) -> ! {
+    serial_print!("\nwrite_protection::test_write_to_text... ");
+
+    nuclea_r_os::gdt::init_gdt();
+    TEST_IDT.load();
+    protection::enable();
+
+    let phys_mem_offset = VirtAddr::new(_boot_info.physical_memory_offset);
+    unsafe {
+        address_space::init(phys_mem_offset);
+        frame::init(&_boot_info.memory_map, phys_mem_offset);
+        protection::protect_kernel();
+    }
+
+    unsafe { (text_address() as *mut u8).write_volatile(0xc3) };
+
+    panic!("[FAILED] - Execution continued after writing to .text.");
+}
+
+/// An address within `.text`, the code of this very function.
+fn text_address() -> u64 {
+    text_address as usize as u64
+}
+
+#[panic_handler]
+fn panic(_info: &PanicInfo) -> ! {
+    nuclea_r_os::test_panic_handler(_info);
+}
+
+extern "x86-interrupt" fn test_page_fault_handler(
+    _stack_frame: InterruptStackFrame,
+    error_code: PageFaultErrorCode,
+) {
+    let expected = PageFaultErrorCode::PROTECTION_VIOLATION | PageFaultErrorCode::CAUSED_BY_WRITE;
+    assert!(
+        error_code.contains(expected),
+        "Unexpected page fault {:?}.",
+        error_code
+    );
+    assert_eq!(Cr2::read().as_u64(), text_address());
+
+    serial_println!("[OK]");
+    serial_println!("Test complete! Exiting.\n");
+
+    exit_qemu(QEMUExitCode::Success);
+
+    nuclea_r_os::hlt_loop();
+}